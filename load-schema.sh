@@ -3,8 +3,23 @@
 source .env
 
 docker exec -i postgres bash -c "
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/extensions.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/plans.sql &&
  psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/users.sql &&
  psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/folders.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/blobs.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/blob_reservations.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/thumbnails.sql &&
  psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/files.sql &&
- psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/get_folder_tree.sql
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/file_versions.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/shares.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/links.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/trash.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/upload_sessions.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/storage_reservations.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/get_folder_tree.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/get_folder_path.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/is_in_trash.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/has_read_access.sql &&
+ psql -U $POSTGRES_USER -d $POSTGRES_DB -f /schema/used_bytes.sql
 "
@@ -171,6 +171,18 @@ namespace API {
     URL.revokeObjectURL(urlBlob);
   }
 
+  export function downloadFolder(id: number): void {
+    // Let the browser download the archive directly, since it's streamed by the server
+    const url = new URL("/api/folder/download", window.location.origin);
+    url.searchParams.set("id", id.toString());
+    const aElem = document.createElement("a");
+    aElem.href = url.href;
+    aElem.style.display = "none";
+    document.body.appendChild(aElem);
+    aElem.click();
+    document.body.removeChild(aElem);
+  }
+
   export async function getFolderSize(id: number, filter: string | null = null): Promise<number> {
     const url = new URL("/api/folder/size", window.location.origin);
     url.searchParams.set("id", id.toString());
@@ -198,6 +210,19 @@ namespace API {
     await rawRequest("DELETE", url.href);
   }
 
+  export async function restoreFile(id: number, isFolder = false): Promise<void> {
+    await rawRequest(
+      "POST",
+      "/api/trash/restore",
+      new Headers({ "content-type": "application/json" }),
+      { id, isFolder }
+    );
+  }
+
+  export async function emptyTrash(): Promise<void> {
+    await rawRequest("DELETE", "/api/trash/empty");
+  }
+
   export async function deleteMe(): Promise<void> {
     await rawRequest("DELETE", "/api/me");
     await logout();
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS blob_reservations (
+  id integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  -- Keeps the blob from being purged while its content is being written
+  fk_blob text REFERENCES blobs(hash) ON DELETE CASCADE NOT NULL,
+  -- Left behind by interrupted uploads when it's over
+  expires_at timestamp NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS blob_reservations_blob_idx ON blob_reservations (fk_blob);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS blobs (
+  hash text PRIMARY KEY,
+  size bigint NOT NULL,
+  ref_count integer NOT NULL CHECK (ref_count >= 0),
+  -- Set by fsck when the content is missing or doesn't have the right size
+  broken boolean NOT NULL DEFAULT false
+);
@@ -0,0 +1,2 @@
+-- Trigram similarity, used for fuzzy name search
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS file_versions (
+  id integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  file_type text,
+  mime_type text NOT NULL,
+  size bigint NOT NULL,
+  last_modified timestamp NOT NULL,
+  fk_file integer REFERENCES files(id) ON DELETE CASCADE NOT NULL,
+  fk_blob text REFERENCES blobs(hash) NOT NULL
+);
@@ -1,10 +1,21 @@
 CREATE TABLE IF NOT EXISTS files (
   id integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
   name text NOT NULL,
+  -- The category of the MIME type, like Text or Image
   file_type text,
-  size integer NOT NULL,
+  mime_type text NOT NULL,
+  size bigint NOT NULL,
   last_modified timestamp NOT NULL,
   starred boolean NOT NULL,
   fk_owner integer REFERENCES users(id) NOT NULL,
-  fk_parent integer REFERENCES folders(id) NOT NULL
+  fk_parent integer REFERENCES folders(id) NOT NULL,
+  fk_blob text REFERENCES blobs(hash) NOT NULL
 );
+
+-- Folder listings are sorted by one of these, with the name and the id breaking ties
+CREATE INDEX IF NOT EXISTS files_parent_name_idx ON files (fk_parent, name, id);
+CREATE INDEX IF NOT EXISTS files_parent_size_idx ON files (fk_parent, size, name, id);
+CREATE INDEX IF NOT EXISTS files_parent_last_modified_idx ON files (fk_parent, last_modified, name, id);
+CREATE INDEX IF NOT EXISTS files_parent_type_idx ON files (fk_parent, COALESCE(file_type, ''), name, id);
+-- Name search matches parts of the names, with trigrams
+CREATE INDEX IF NOT EXISTS files_name_trgm_idx ON files USING gin (name gin_trgm_ops);
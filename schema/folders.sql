@@ -6,3 +6,7 @@ CREATE TABLE IF NOT EXISTS folders (
   fk_owner integer REFERENCES users(id) NOT NULL,
   fk_parent integer REFERENCES folders(id)
 );
+
+-- Folder listings are sorted by one of these, with the name and the id breaking ties
+CREATE INDEX IF NOT EXISTS folders_parent_name_idx ON folders (fk_parent, name, id);
+CREATE INDEX IF NOT EXISTS folders_parent_last_modified_idx ON folders (fk_parent, last_modified, name, id);
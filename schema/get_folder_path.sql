@@ -0,0 +1,15 @@
+CREATE OR REPLACE FUNCTION get_folder_path(p_folder_id INT)
+RETURNS TABLE(folder_id INT, name TEXT, depth INT) AS $$
+BEGIN
+    RETURN QUERY WITH RECURSIVE folder_path AS (
+        SELECT id, fk_parent, folders.name, 0 AS depth
+        FROM folders
+        WHERE id = p_folder_id
+        UNION
+        SELECT f.id, f.fk_parent, f.name, fp.depth + 1
+        FROM folders f
+        JOIN folder_path fp ON f.id = fp.fk_parent
+    )
+    SELECT folder_path.id, folder_path.name, folder_path.depth FROM folder_path;
+END; $$ 
+LANGUAGE plpgsql;
@@ -0,0 +1,23 @@
+CREATE OR REPLACE FUNCTION has_folder_read_access(p_folder_id INT, p_user_id INT)
+RETURNS boolean AS $$
+BEGIN
+    RETURN EXISTS (
+        SELECT 1
+        FROM shares s
+        WHERE s.fk_user = p_user_id AND s.fk_folder IS NOT NULL
+        AND p_folder_id IN (SELECT folder_id FROM get_folder_tree(s.fk_folder, s.fk_owner))
+    );
+END; $$ 
+-- Only reads, so the planner can reuse the result within a statement
+LANGUAGE plpgsql STABLE;
+
+CREATE OR REPLACE FUNCTION has_file_read_access(p_file_id INT, p_user_id INT)
+RETURNS boolean AS $$
+BEGIN
+    RETURN EXISTS (
+        SELECT 1
+        FROM shares s
+        WHERE s.fk_user = p_user_id AND s.fk_file = p_file_id
+    ) OR has_folder_read_access((SELECT fk_parent FROM files WHERE id = p_file_id), p_user_id);
+END; $$ 
+LANGUAGE plpgsql STABLE;
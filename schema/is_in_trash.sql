@@ -0,0 +1,29 @@
+CREATE OR REPLACE FUNCTION is_in_trash(p_folder_id INT)
+RETURNS boolean AS $$
+BEGIN
+    RETURN EXISTS (
+        SELECT 1
+        FROM get_folder_path(p_folder_id) p
+        JOIN folders f ON f.id = p.folder_id
+        WHERE f.fk_parent IS NULL AND f.name = 'Trash'
+    );
+END; $$ 
+LANGUAGE plpgsql;
+
+-- All the folders in the trash of the user, the Trash folder included.
+-- Lists of items are filtered with it at once, instead of calling is_in_trash for each item.
+CREATE OR REPLACE FUNCTION get_trash_tree(p_owner_id INT)
+RETURNS TABLE(folder_id INT) AS $$
+BEGIN
+    RETURN QUERY WITH RECURSIVE trash_tree AS (
+        SELECT id
+        FROM folders
+        WHERE fk_owner = p_owner_id AND fk_parent IS NULL AND name = 'Trash'
+        UNION
+        SELECT f.id
+        FROM folders f
+        JOIN trash_tree tt ON f.fk_parent = tt.id
+    )
+    SELECT trash_tree.id FROM trash_tree;
+END; $$
+LANGUAGE plpgsql;
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS links (
+  id integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  token text UNIQUE NOT NULL,
+  fk_file integer REFERENCES files(id) ON DELETE CASCADE,
+  fk_folder integer REFERENCES folders(id) ON DELETE CASCADE,
+  fk_owner integer REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+  password text,
+  expires_at timestamp,
+  max_downloads integer CHECK (max_downloads > 0),
+  downloads integer NOT NULL,
+  created_at timestamp NOT NULL,
+  CHECK ((fk_file IS NULL) <> (fk_folder IS NULL))
+);
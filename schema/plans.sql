@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS plans (
+  id integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  name text NOT NULL UNIQUE,
+  max_storage_mb bigint NOT NULL CHECK (max_storage_mb >= 0)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS shares (
+  id integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  fk_file integer REFERENCES files(id) ON DELETE CASCADE,
+  fk_folder integer REFERENCES folders(id) ON DELETE CASCADE,
+  fk_owner integer REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+  fk_user integer REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+  CHECK ((fk_file IS NULL) <> (fk_folder IS NULL)),
+  UNIQUE (fk_file, fk_user),
+  UNIQUE (fk_folder, fk_user)
+);
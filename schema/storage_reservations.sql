@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS storage_reservations (
+  id integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  bytes bigint NOT NULL CHECK (bytes >= 0),
+  -- Left behind by interrupted uploads when it's over
+  expires_at timestamp NOT NULL,
+  fk_user integer REFERENCES users(id) ON DELETE CASCADE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS storage_reservations_user_idx ON storage_reservations (fk_user);
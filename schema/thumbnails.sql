@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS thumbnails (
+  fk_blob text REFERENCES blobs(hash) ON DELETE CASCADE NOT NULL,
+  -- The largest side in pixels
+  size integer NOT NULL,
+  mime_type text NOT NULL,
+  bytes integer NOT NULL,
+  PRIMARY KEY (fk_blob, size)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS trash (
+  id integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  fk_file integer REFERENCES files(id) ON DELETE CASCADE UNIQUE,
+  fk_folder integer REFERENCES folders(id) ON DELETE CASCADE UNIQUE,
+  fk_owner integer REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+  fk_original_parent integer REFERENCES folders(id) ON DELETE SET NULL,
+  original_path text[] NOT NULL,
+  trashed_at timestamp NOT NULL,
+  CHECK ((fk_file IS NULL) <> (fk_folder IS NULL))
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS upload_sessions (
+  id text PRIMARY KEY,
+  name text NOT NULL,
+  size bigint NOT NULL,
+  created_at timestamp NOT NULL,
+  fk_owner integer REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+  fk_parent integer REFERENCES folders(id) ON DELETE CASCADE NOT NULL
+);
@@ -0,0 +1,64 @@
+-- The bytes that count against the quota of the user: their files and the versions of their files
+CREATE OR REPLACE FUNCTION get_used_bytes(p_user_id INT)
+RETURNS bigint AS $$
+BEGIN
+    RETURN (SELECT COALESCE(SUM(size), 0) FROM files WHERE fk_owner = p_user_id)
+        + (SELECT COALESCE(SUM(v.size), 0)
+            FROM file_versions v
+            JOIN files f ON f.id = v.fk_file
+            WHERE f.fk_owner = p_user_id);
+END; $$
+LANGUAGE plpgsql;
+
+-- Keep users.used_bytes up to date, in the same transaction as the change of the files
+CREATE OR REPLACE FUNCTION count_file_bytes()
+RETURNS trigger AS $$
+DECLARE
+    versions_size bigint := 0;
+BEGIN
+    -- The versions are deleted with the file, once the file can't be found anymore,
+    -- and they follow the file when it changes owner
+    IF TG_OP = 'DELETE' OR (TG_OP = 'UPDATE' AND OLD.fk_owner <> NEW.fk_owner) THEN
+        SELECT COALESCE(SUM(size), 0) INTO versions_size
+        FROM file_versions
+        WHERE fk_file = OLD.id;
+    END IF;
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE users SET used_bytes = used_bytes - OLD.size - versions_size
+        WHERE id = OLD.fk_owner;
+    END IF;
+    IF TG_OP = 'DELETE' THEN
+        RETURN OLD;
+    END IF;
+    UPDATE users SET used_bytes = used_bytes + NEW.size + versions_size
+    WHERE id = NEW.fk_owner;
+    RETURN NEW;
+END; $$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION count_version_bytes()
+RETURNS trigger AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE users SET used_bytes = used_bytes - OLD.size
+        WHERE id = (SELECT fk_owner FROM files WHERE id = OLD.fk_file);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        UPDATE users SET used_bytes = used_bytes + NEW.size
+        WHERE id = (SELECT fk_owner FROM files WHERE id = NEW.fk_file);
+    END IF;
+    RETURN NULL;
+END; $$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER files_used_bytes_delete
+BEFORE DELETE ON files
+FOR EACH ROW EXECUTE FUNCTION count_file_bytes();
+
+CREATE OR REPLACE TRIGGER files_used_bytes
+AFTER INSERT OR UPDATE OF size, fk_owner ON files
+FOR EACH ROW EXECUTE FUNCTION count_file_bytes();
+
+CREATE OR REPLACE TRIGGER file_versions_used_bytes
+AFTER INSERT OR DELETE OR UPDATE OF size, fk_file ON file_versions
+FOR EACH ROW EXECUTE FUNCTION count_version_bytes();
@@ -2,5 +2,10 @@ CREATE TABLE IF NOT EXISTS users (
   id integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
   username text NOT NULL UNIQUE,
   email text NOT NULL UNIQUE,
-  password text NOT NULL
+  password text NOT NULL,
+  -- Overrides the quota of the plan, MAX_STORAGE_MB is used when there's neither
+  max_storage_mb bigint CHECK (max_storage_mb >= 0),
+  fk_plan integer REFERENCES plans(id) ON DELETE SET NULL,
+  -- Kept up to date by the triggers of used_bytes.sql
+  used_bytes bigint NOT NULL DEFAULT 0
 );
@@ -0,0 +1,237 @@
+use crate::{
+    errors::InternalError,
+    models::{
+        folders_model, plans_model, sessions_model, users_model, versions_model, Plan, RedisPool,
+        User,
+    },
+    storage::StorageBackend,
+};
+use sqlx::PgPool;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// A command to manage the users without starting the server.
+/// The users are given by username or by email.
+pub enum Command {
+    ListUsers,
+    Usage(String),
+    // The password is read from the standard input
+    ResetPassword(String),
+    DeleteUser(String),
+    SetQuota(String, Option<i64>),
+    Logout(String),
+    ListPlans,
+    SetPlan(String, i64),
+    DeletePlan(String),
+    ChangePlan(String, Option<String>),
+}
+
+impl Command {
+    /// Returns the command given by the arguments, if they are valid.
+    pub fn parse(args: &[&str]) -> Option<Command> {
+        let command = match *args {
+            ["users"] => Command::ListUsers,
+            ["usage", user] => Command::Usage(user.to_string()),
+            ["reset-password", user] => Command::ResetPassword(user.to_string()),
+            ["delete-user", user] => Command::DeleteUser(user.to_string()),
+            ["set-quota", user, "default"] => Command::SetQuota(user.to_string(), None),
+            ["set-quota", user, mb] => {
+                let mb = mb.parse::<i64>().ok().filter(|mb| *mb >= 0)?;
+                Command::SetQuota(user.to_string(), Some(mb))
+            }
+            ["logout", user] => Command::Logout(user.to_string()),
+            ["plans"] => Command::ListPlans,
+            ["set-plan", plan, mb] => {
+                let mb = mb.parse::<i64>().ok().filter(|mb| *mb >= 0)?;
+                Command::SetPlan(plan.to_string(), mb)
+            }
+            ["delete-plan", plan] => Command::DeletePlan(plan.to_string()),
+            ["change-plan", user, "none"] => Command::ChangePlan(user.to_string(), None),
+            ["change-plan", user, plan] => {
+                Command::ChangePlan(user.to_string(), Some(plan.to_string()))
+            }
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    pub async fn run(
+        self,
+        pg_pool: &PgPool,
+        redis_pool: &RedisPool,
+        storage: &dyn StorageBackend,
+    ) -> Result<(), InternalError> {
+        match self {
+            Command::ListUsers => list_users(pg_pool).await,
+            Command::Usage(user) => show_usage(pg_pool, &user).await,
+            Command::ResetPassword(user) => {
+                let user = find_user(pg_pool, &user).await?;
+                let password = read_password().await?;
+                users_model::set_password(pg_pool, user.get_id(), &password)
+                    .await
+                    .map_err(|e| InternalError(format!("Failed to reset the password: {}", e)))?;
+                // Whoever had the old password is logged out
+                let count = sessions_model::delete_user_sessions(redis_pool, user.get_id()).await?;
+                println!(
+                    "Reset the password of '{}' and ended {} sessions",
+                    user.get_username(),
+                    count
+                );
+                Ok(())
+            }
+            Command::DeleteUser(user) => {
+                let user = find_user(pg_pool, &user).await?;
+                users_model::delete_user(pg_pool, storage, user.get_id()).await?;
+                sessions_model::delete_user_sessions(redis_pool, user.get_id()).await?;
+                println!("Deleted '{}' and all their files", user.get_username());
+                Ok(())
+            }
+            Command::SetQuota(user, max_storage_mb) => {
+                let user = find_user(pg_pool, &user).await?;
+                users_model::set_max_storage(pg_pool, user.get_id(), max_storage_mb).await?;
+                match max_storage_mb {
+                    Some(mb) => println!("Set the quota of '{}' to {} MB", user.get_username(), mb),
+                    None => println!(
+                        "Removed the quota of '{}', the one of their plan or the default one is used",
+                        user.get_username()
+                    ),
+                }
+                Ok(())
+            }
+            Command::Logout(user) => {
+                let user = find_user(pg_pool, &user).await?;
+                let count = sessions_model::delete_user_sessions(redis_pool, user.get_id()).await?;
+                println!("Ended {} sessions of '{}'", count, user.get_username());
+                Ok(())
+            }
+            Command::ListPlans => list_plans(pg_pool).await,
+            Command::SetPlan(plan, mb) => {
+                plans_model::set_plan(pg_pool, &plan, mb).await?;
+                println!("Set the quota of the '{}' plan to {} MB", plan, mb);
+                Ok(())
+            }
+            Command::DeletePlan(plan) => {
+                let plan = find_plan(pg_pool, &plan).await?;
+                plans_model::delete_plan(pg_pool, plan.get_id()).await?;
+                println!(
+                    "Deleted the '{}' plan and removed its {} users from it",
+                    plan.get_name(),
+                    plan.get_users()
+                );
+                Ok(())
+            }
+            Command::ChangePlan(user, plan) => {
+                let user = find_user(pg_pool, &user).await?;
+                let plan = match plan {
+                    Some(plan) => Some(find_plan(pg_pool, &plan).await?),
+                    None => None,
+                };
+                users_model::set_plan(pg_pool, user.get_id(), plan.as_ref().map(|p| p.get_id()))
+                    .await?;
+                match plan {
+                    Some(plan) => println!(
+                        "Put '{}' on the '{}' plan",
+                        user.get_username(),
+                        plan.get_name()
+                    ),
+                    None => println!("Removed '{}' from their plan", user.get_username()),
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn find_user(pg_pool: &PgPool, user: &str) -> Result<User, InternalError> {
+    let found = if user.contains('@') {
+        users_model::get_user_by_email(pg_pool, user).await?
+    } else {
+        users_model::get_user_by_username(pg_pool, user).await?
+    };
+    found.ok_or_else(|| InternalError(format!("User '{}' not found", user)))
+}
+
+async fn find_plan(pg_pool: &PgPool, plan: &str) -> Result<Plan, InternalError> {
+    plans_model::get_plan_by_name(pg_pool, plan)
+        .await?
+        .ok_or_else(|| InternalError(format!("Plan '{}' not found", plan)))
+}
+
+async fn list_users(pg_pool: &PgPool) -> Result<(), InternalError> {
+    let users = users_model::get_users(pg_pool).await?;
+    println!(
+        "{:>6}  {:<20}  {:<32}  {:<12}  {:>12}  {:>18}",
+        "ID", "USERNAME", "EMAIL", "PLAN", "USED", "QUOTA"
+    );
+    for user in &users {
+        let used = users_model::get_used_storage(pg_pool, user.get_id()).await?;
+        println!(
+            "{:>6}  {:<20}  {:<32}  {:<12}  {:>12}  {:>18}",
+            user.get_id(),
+            user.get_username(),
+            user.get_email(),
+            user.get_plan().as_deref().unwrap_or("-"),
+            format_mb(used),
+            format_quota(user)
+        );
+    }
+    println!("{} users", users.len());
+    Ok(())
+}
+
+async fn list_plans(pg_pool: &PgPool) -> Result<(), InternalError> {
+    println!("{:<20}  {:>12}  {:>8}", "PLAN", "QUOTA", "USERS");
+    for plan in plans_model::get_plans(pg_pool).await? {
+        println!(
+            "{:<20}  {:>12}  {:>8}",
+            plan.get_name(),
+            format!("{} MB", plan.get_max_storage_mb()),
+            plan.get_users()
+        );
+    }
+    Ok(())
+}
+
+async fn show_usage(pg_pool: &PgPool, user: &str) -> Result<(), InternalError> {
+    let user = find_user(pg_pool, user).await?;
+    for folder in folders_model::get_root_folders(pg_pool, user.get_id()).await? {
+        let size =
+            folders_model::folder_size(pg_pool, folder.get_id(), user.get_id(), None).await?;
+        println!("{:<16}{:>12}", folder.get_name(), format_mb(size));
+    }
+    let versions = versions_model::get_versions_size(pg_pool, user.get_id()).await?;
+    println!("{:<16}{:>12}", "Versions", format_mb(versions));
+    // Not the sum of the above when identical content only counts once
+    let used = users_model::get_used_storage(pg_pool, user.get_id()).await?;
+    println!("Using {} of {}", format_mb(used), format_quota(&user));
+    Ok(())
+}
+
+fn format_mb(bytes: i64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
+
+fn format_quota(user: &User) -> String {
+    if user.has_own_quota() {
+        format!("{} MB", user.get_max_storage_mb())
+    } else if user.get_plan().is_some() {
+        format!("{} MB (plan)", user.get_max_storage_mb())
+    } else {
+        format!("{} MB (default)", user.get_max_storage_mb())
+    }
+}
+
+/// Reads the new password from the first line of the standard input, so that it doesn't show
+/// in the shell history or the list of processes.
+async fn read_password() -> Result<String, InternalError> {
+    eprint!("New password: ");
+    let mut line = String::new();
+    BufReader::new(tokio::io::stdin())
+        .read_line(&mut line)
+        .await
+        .map_err(|_| InternalError("Failed to read the password".to_string()))?;
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(InternalError("The password can't be empty".to_string()));
+    }
+    Ok(password.to_string())
+}
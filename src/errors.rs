@@ -47,6 +47,8 @@ impl Error for LoginError {}
 #[derive(Debug)]
 pub enum FileError {
     NameError,
+    // The folder the item goes in isn't one of the user's
+    NotFound,
     InternalError,
 }
 
@@ -54,6 +56,7 @@ impl Display for FileError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             FileError::NameError => f.write_str("Invalid file name"),
+            FileError::NotFound => f.write_str("Folder not found"),
             FileError::InternalError => f.write_str("Internal error"),
         }
     }
@@ -61,6 +64,106 @@ impl Display for FileError {
 
 impl Error for FileError {}
 
+#[derive(Debug)]
+pub enum ShareError {
+    NotFound,
+    UserNotFound,
+    SelfShare,
+    AlreadyShared,
+    InternalError,
+}
+
+impl Display for ShareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShareError::NotFound => f.write_str("File or folder not found"),
+            ShareError::UserNotFound => f.write_str("User not found"),
+            ShareError::SelfShare => f.write_str("Can't share with yourself"),
+            ShareError::AlreadyShared => f.write_str("Already shared with this user"),
+            ShareError::InternalError => f.write_str("Internal error"),
+        }
+    }
+}
+
+impl Error for ShareError {}
+
+#[derive(Debug)]
+pub enum LinkError {
+    NotFound,
+    WrongPassword,
+    InternalError,
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LinkError::NotFound => f.write_str("Link not found"),
+            LinkError::WrongPassword => f.write_str("Wrong password"),
+            LinkError::InternalError => f.write_str("Internal error"),
+        }
+    }
+}
+
+impl Error for LinkError {}
+
+#[derive(Debug)]
+pub enum TrashError {
+    NotFound,
+    NotInTrash,
+    // Root folders stay where they are
+    RootFolder,
+    InternalError,
+}
+
+impl Display for TrashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TrashError::NotFound => f.write_str("File or folder not found"),
+            TrashError::NotInTrash => f.write_str("Not in the trash"),
+            TrashError::RootFolder => f.write_str("Root folders can't be deleted"),
+            TrashError::InternalError => f.write_str("Internal error"),
+        }
+    }
+}
+
+impl Error for TrashError {}
+
+#[derive(Debug)]
+pub enum QuotaError {
+    // With the number of bytes left
+    NotEnoughSpace(i64),
+    InternalError,
+}
+
+impl Display for QuotaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            QuotaError::NotEnoughSpace(_) => f.write_str("Not enough space"),
+            QuotaError::InternalError => f.write_str("Internal error"),
+        }
+    }
+}
+
+impl Error for QuotaError {}
+
+#[derive(Debug)]
+pub enum ThumbnailError {
+    // Not an image, or one that can't be decoded
+    Unsupported,
+    InternalError,
+}
+
+impl Display for ThumbnailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ThumbnailError::Unsupported => f.write_str("No thumbnail for this file"),
+            ThumbnailError::InternalError => f.write_str("Internal error"),
+        }
+    }
+}
+
+impl Error for ThumbnailError {}
+
 #[derive(Debug)]
 pub struct InternalError(pub String);
 
@@ -0,0 +1,148 @@
+use crate::{
+    errors::InternalError,
+    models::{blobs_model, thumbnails_model, users_model},
+    storage::StorageBackend,
+};
+use sqlx::PgPool;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+use tokio::{fs, io};
+
+/// Where the content that doesn't belong to any blob is moved when repairing.
+const QUARANTINE_FOLDER: &str = "quarantine";
+
+/// Checks that the stored content matches the blobs of the database, and repairs what can be
+/// if asked to. Returns the number of problems that were found.
+pub async fn fsck(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    repair: bool,
+) -> Result<usize, InternalError> {
+    // The content is listed first: blobs are recorded before their content is written,
+    // so the blob of any content that is listed is already in the database
+    let stored: HashMap<String, u64> = storage.list().await?.into_iter().collect();
+    let blobs = blobs_model::get_blobs(pg_pool).await?;
+    let thumbnails = thumbnails_model::get_thumbnail_keys(pg_pool).await?;
+    let mut problems = 0;
+    // Missing thumbnails aren't a problem, they are made again when needed
+    let known: HashSet<&String> = blobs
+        .iter()
+        .map(|b| b.get_hash())
+        .chain(thumbnails.iter())
+        .collect();
+    for (key, size) in &stored {
+        if !known.contains(key) {
+            problems += 1;
+            println!("Orphaned content '{}' ({} bytes)", key, size);
+            if repair {
+                quarantine(storage, key).await?;
+            }
+        }
+    }
+    for blob in &blobs {
+        let hash = blob.get_hash();
+        if blob.get_references() != i64::from(blob.get_ref_count()) {
+            problems += 1;
+            println!(
+                "Blob '{}' counts {} references but is used {} times",
+                hash,
+                blob.get_ref_count(),
+                blob.get_references()
+            );
+            if repair {
+                blobs_model::fix_ref_count(pg_pool, hash).await?;
+            }
+        }
+        // Unused blobs are waiting to be purged, their content may already be gone
+        if blob.get_references() == 0 {
+            continue;
+        }
+        let broken = match stored.get(hash) {
+            None => {
+                println!("Missing content for blob '{}'", hash);
+                true
+            }
+            Some(&size) if size as i64 != blob.get_size() => {
+                println!(
+                    "Content of blob '{}' is {} bytes instead of {}",
+                    hash,
+                    size,
+                    blob.get_size()
+                );
+                true
+            }
+            Some(_) => false,
+        };
+        if broken != blob.is_broken() {
+            if !broken {
+                println!(
+                    "Blob '{}' is marked as broken but its content is fine",
+                    hash
+                );
+            }
+            problems += 1;
+            if repair {
+                blobs_model::set_blob_broken(pg_pool, hash, broken).await?;
+            }
+        } else if broken {
+            // Already marked by a previous repair
+            problems += 1;
+        }
+    }
+    let wrong_sizes = blobs_model::get_wrong_sizes(pg_pool).await?;
+    for w in &wrong_sizes {
+        problems += 1;
+        let item = match w.get_version_id() {
+            Some(version_id) => format!("Version {} of file", version_id),
+            None => "File".to_string(),
+        };
+        println!(
+            "{} {} '{}' is {} bytes but its content is {} bytes",
+            item,
+            w.get_file_id(),
+            w.get_name(),
+            w.get_size(),
+            w.get_blob_size()
+        );
+    }
+    if repair {
+        // The counts of used bytes follow the sizes
+        blobs_model::fix_sizes(pg_pool).await?;
+    }
+    for w in users_model::get_wrong_usages(pg_pool).await? {
+        problems += 1;
+        println!(
+            "User '{}' counts {} used bytes but uses {}",
+            w.get_username(),
+            w.get_used_bytes(),
+            w.get_actual_bytes()
+        );
+        if repair {
+            users_model::fix_used_storage(pg_pool, w.get_user_id()).await?;
+        }
+    }
+    if repair {
+        // Including the blobs whose references were just fixed
+        let purged = blobs_model::purge_unused_blobs(pg_pool, storage).await?;
+        if purged > 0 {
+            println!("Purged {} unused blobs", purged);
+        }
+    }
+    Ok(problems)
+}
+
+/// Moves the content out of the storage, into the quarantine folder.
+async fn quarantine(storage: &dyn StorageBackend, key: &str) -> Result<(), InternalError> {
+    let err = |_| InternalError(format!("Failed to quarantine content '{}'", key));
+    fs::create_dir_all(QUARANTINE_FOLDER).await.map_err(err)?;
+    let path = PathBuf::from(QUARANTINE_FOLDER).join(key.replace(['/', '\\'], "_"));
+    let mut content = storage.get(key, None).await?;
+    let mut file = fs::File::create(&path).await.map_err(err)?;
+    io::copy(&mut content, &mut file).await.map_err(err)?;
+    file.sync_all().await.map_err(err)?;
+    storage.delete(key).await?;
+    println!("Moved '{}' to '{}'", key, path.display());
+    Ok(())
+}
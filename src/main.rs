@@ -1,14 +1,20 @@
+mod admin;
 mod errors;
+mod fsck;
+mod migrate;
 mod models;
 mod routes;
+mod storage;
 
 use lazy_static::lazy_static;
-use models::{init_files_folder, init_postgres, init_redis};
+use models::{init_files_folder, init_maintenance, init_postgres, init_redis};
 use rand_chacha::ChaCha8Rng;
 use rand_core::{OsRng, RngCore, SeedableRng};
 use routes::create_routes;
 use std::env;
 use std::net::SocketAddr;
+use std::process::ExitCode;
+use storage::init_storage;
 
 lazy_static! {
     // Load environment variables from .env file
@@ -28,26 +34,98 @@ lazy_static! {
         .expect("MAX_UPLOAD_MB missing in .env")
         .parse()
         .expect("MAX_UPLOAD_MB must be a usize");
+    // Files uploaded in chunks aren't limited by the size of a request
+    pub static ref MAX_RESUMABLE_UPLOAD_MB: i64 = env::var("MAX_RESUMABLE_UPLOAD_MB")
+        .expect("MAX_RESUMABLE_UPLOAD_MB missing in .env")
+        .parse()
+        .expect("MAX_RESUMABLE_UPLOAD_MB must be a i64");
     pub static ref MAX_STORAGE_MB: i64 = env::var("MAX_STORAGE_MB")
         .expect("MAX_STORAGE_MB missing in .env")
         .parse()
         .expect("MAX_STORAGE_MB must be a i64");
+    pub static ref TRASH_RETENTION_DAYS: i32 = env::var("TRASH_RETENTION_DAYS")
+        .expect("TRASH_RETENTION_DAYS missing in .env")
+        .parse()
+        .expect("TRASH_RETENTION_DAYS must be a i32");
+    pub static ref PHYSICAL_QUOTA: bool = env::var("PHYSICAL_QUOTA")
+        .expect("PHYSICAL_QUOTA missing in .env")
+        .parse()
+        .expect("PHYSICAL_QUOTA must be a bool");
+    pub static ref STORAGE_BACKEND: String = env::var("STORAGE_BACKEND")
+        .expect("STORAGE_BACKEND missing in .env");
+    // Only needed by the S3 storage backend
+    pub static ref S3_ENDPOINT: String = env::var("S3_ENDPOINT")
+        .expect("S3_ENDPOINT missing in .env");
+    pub static ref S3_REGION: String = env::var("S3_REGION")
+        .expect("S3_REGION missing in .env");
+    pub static ref S3_BUCKET: String = env::var("S3_BUCKET")
+        .expect("S3_BUCKET missing in .env");
+    pub static ref S3_ACCESS_KEY: String = env::var("S3_ACCESS_KEY")
+        .expect("S3_ACCESS_KEY missing in .env");
+    pub static ref S3_SECRET_KEY: String = env::var("S3_SECRET_KEY")
+        .expect("S3_SECRET_KEY missing in .env");
 }
 
+const USAGE: &str = "Usage: cloud-storage-system [COMMAND]
+
+Starts the server when there's no command.
+
+Commands:
+  fsck [--repair]                 Check the stored content against the database
+  migrate                         Upgrade a database created by an earlier version
+  users                           List the users and their storage usage
+  usage <user>                    Show the storage usage of a user
+  reset-password <user>           Set a new password, read from stdin, and log the user out
+  delete-user <user>              Delete a user and all their files
+  set-quota <user> <MB|default>   Set the storage quota of a user, over the one of the plan
+  logout <user>                   End all the sessions of a user
+  plans                           List the plans and how many users are on them
+  set-plan <plan> <MB>            Create a plan or change its storage quota
+  delete-plan <plan>              Delete a plan, its users get the default quota
+  change-plan <user> <plan|none>  Put a user on a plan
+
+Users are given by username or by email.";
+
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     // Load .env file
     dotenvy::dotenv().expect("Failed to load .env");
+    // Without a command, the server is started
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] => {
+            serve().await;
+            ExitCode::SUCCESS
+        }
+        ["fsck"] => check_storage(false).await,
+        ["fsck", "--repair"] => check_storage(true).await,
+        ["migrate"] => migrate_database().await,
+        _ => match admin::Command::parse(&args) {
+            Some(command) => run_admin(command).await,
+            None => {
+                eprintln!("{}", USAGE);
+                ExitCode::from(2)
+            }
+        },
+    }
+}
+
+async fn serve() {
     // Initialize postgres
     let pg_pool = init_postgres(&DATABASE_URL, *DB_MAX_CONNECTIONS).await;
     // Initialize redis
     let redis_pool = init_redis(&REDIS_URL).await;
     // Intialize the folder with the actual files
     init_files_folder().await;
+    // Initialize the storage of the files content
+    let storage = init_storage(&STORAGE_BACKEND);
+    // Start the periodic cleanup, which also empties the trash
+    init_maintenance(pg_pool.clone(), storage.clone(), *TRASH_RETENTION_DAYS);
     // Initialize ChaCha algorithm
     let rng = ChaCha8Rng::seed_from_u64(OsRng.next_u64());
     // Initalize the controller
-    let app = create_routes(pg_pool, redis_pool, rng);
+    let app = create_routes(pg_pool, redis_pool, rng, storage);
     // IP address and port of the server
     let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
     // Start the server
@@ -57,3 +135,69 @@ async fn main() {
         .await
         .unwrap();
 }
+
+/// Checks the stored content against the database, without starting the server.
+/// Fails if problems were found and not repaired.
+async fn check_storage(repair: bool) -> ExitCode {
+    let pg_pool = init_postgres(&DATABASE_URL, *DB_MAX_CONNECTIONS).await;
+    init_files_folder().await;
+    let storage = init_storage(&STORAGE_BACKEND);
+    match fsck::fsck(&pg_pool, storage.as_ref(), repair).await {
+        Ok(0) => {
+            println!("No problems found");
+            ExitCode::SUCCESS
+        }
+        Ok(problems) if repair => {
+            println!("Found {} problems, repaired what could be", problems);
+            ExitCode::SUCCESS
+        }
+        Ok(problems) => {
+            println!("Found {} problems, run with --repair to fix them", problems);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Upgrades the database and moves the stored content, without starting the server.
+/// Fails if some files couldn't be migrated, it can then be run again.
+async fn migrate_database() -> ExitCode {
+    let pg_pool = init_postgres(&DATABASE_URL, *DB_MAX_CONNECTIONS).await;
+    init_files_folder().await;
+    let storage = init_storage(&STORAGE_BACKEND);
+    match migrate::migrate(&pg_pool, storage.as_ref()).await {
+        Ok(0) => {
+            println!("The database is up to date");
+            ExitCode::SUCCESS
+        }
+        Ok(failed) => {
+            println!(
+                "{} files couldn't be migrated, run it again once they're fixed",
+                failed
+            );
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs an administration command, without starting the server.
+async fn run_admin(command: admin::Command) -> ExitCode {
+    let pg_pool = init_postgres(&DATABASE_URL, *DB_MAX_CONNECTIONS).await;
+    let redis_pool = init_redis(&REDIS_URL).await;
+    init_files_folder().await;
+    let storage = init_storage(&STORAGE_BACKEND);
+    match command.run(&pg_pool, &redis_pool, storage.as_ref()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
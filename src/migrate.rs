@@ -0,0 +1,137 @@
+use crate::{
+    errors::InternalError,
+    models::{files_model, users_model, TempFile},
+    storage::StorageBackend,
+};
+use rand_core::{OsRng, RngCore};
+use sqlx::{Executor, PgPool};
+use tokio::io::AsyncReadExt;
+
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The columns added to the tables that existed before, they can't be mandatory until the
+/// content of the files is migrated.
+const ADD_COLUMNS: &str = "
+ALTER TABLE users
+  ADD COLUMN IF NOT EXISTS max_storage_mb bigint CHECK (max_storage_mb >= 0),
+  ADD COLUMN IF NOT EXISTS fk_plan integer REFERENCES plans(id) ON DELETE SET NULL,
+  ADD COLUMN IF NOT EXISTS used_bytes bigint NOT NULL DEFAULT 0;
+ALTER TABLE files
+  ADD COLUMN IF NOT EXISTS mime_type text,
+  ADD COLUMN IF NOT EXISTS fk_blob text REFERENCES blobs(hash);
+ALTER TABLE blobs
+  ADD COLUMN IF NOT EXISTS broken boolean NOT NULL DEFAULT false;
+";
+
+/// The sizes used to be integers. The triggers that use them are created again afterwards.
+const WIDEN_SIZES: &str = "
+DROP TRIGGER IF EXISTS files_used_bytes ON files;
+DROP TRIGGER IF EXISTS file_versions_used_bytes ON file_versions;
+ALTER TABLE files
+  ALTER COLUMN size TYPE bigint;
+ALTER TABLE file_versions
+  ALTER COLUMN size TYPE bigint;
+";
+
+/// The indexes added to the tables that existed before.
+const ADD_INDEXES: &str = "
+CREATE INDEX IF NOT EXISTS files_name_trgm_idx ON files USING gin (name gin_trgm_ops);
+";
+
+const USED_BYTES_SCHEMA: &str = include_str!("../schema/used_bytes.sql");
+
+const SET_NOT_NULL: &str = "
+ALTER TABLE files
+  ALTER COLUMN mime_type SET NOT NULL,
+  ALTER COLUMN fk_blob SET NOT NULL;
+";
+
+/// The items that were already in the trash are kept for the whole retention period, starting now.
+const TRACK_TRASH: &str = "
+INSERT INTO trash (fk_file, fk_owner, original_path, trashed_at)
+SELECT f.id, f.fk_owner, '{}', CURRENT_TIMESTAMP
+FROM files f
+JOIN folders t ON t.id = f.fk_parent
+WHERE t.fk_parent IS null AND t.name = 'Trash'
+ON CONFLICT DO NOTHING;
+INSERT INTO trash (fk_folder, fk_owner, original_path, trashed_at)
+SELECT f.id, f.fk_owner, '{}', CURRENT_TIMESTAMP
+FROM folders f
+JOIN folders t ON t.id = f.fk_parent
+WHERE t.fk_parent IS null AND t.name = 'Trash'
+ON CONFLICT DO NOTHING;
+";
+
+/// Upgrades a database created by an earlier version, once the new tables have been created
+/// by load-schema.sh. The content of the files is moved from their id to their hash.
+/// Returns the number of files that couldn't be migrated, the upgrade is only completed when
+/// there's none, and it can be run again.
+pub async fn migrate(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+) -> Result<usize, InternalError> {
+    // All at once, so that the triggers aren't left out if something fails
+    pg_pool
+        .execute(
+            [ADD_COLUMNS, WIDEN_SIZES, ADD_INDEXES, USED_BYTES_SCHEMA]
+                .concat()
+                .as_str(),
+        )
+        .await
+        .map_err(|e| InternalError(format!("Failed to add the new columns: {}", e)))?;
+    let files = files_model::get_legacy_files(pg_pool).await?;
+    let mut failed = 0;
+    for (file_id, name) in &files {
+        if let Err(e) = migrate_content(pg_pool, storage, *file_id, name).await {
+            failed += 1;
+            println!("File {} '{}' wasn't migrated: {}", file_id, name, e);
+        }
+    }
+    println!("Moved the content of {} files", files.len() - failed);
+    if failed > 0 {
+        return Ok(failed);
+    }
+    pg_pool
+        .execute(SET_NOT_NULL)
+        .await
+        .map_err(|e| InternalError(format!("Failed to make the new columns mandatory: {}", e)))?;
+    pg_pool
+        .execute(TRACK_TRASH)
+        .await
+        .map_err(|e| InternalError(format!("Failed to record the trash: {}", e)))?;
+    // The storage used by the users was never counted
+    users_model::reconcile_used_storage(pg_pool).await?;
+    Ok(0)
+}
+
+/// Copies the content stored under the id of the file to its blob, and deletes it once the
+/// file uses the blob.
+async fn migrate_content(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    file_id: i32,
+    name: &str,
+) -> Result<(), InternalError> {
+    let key = file_id.to_string();
+    let mut reader = storage.get(&key, None).await?;
+    let mut content = TempFile::create(OsRng.next_u64()).await?;
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer).await {
+            Ok(read) => read,
+            Err(_) => {
+                content.discard().await;
+                return Err(InternalError(format!("Failed to read content '{}'", key)));
+            }
+        };
+        if read == 0 {
+            break;
+        }
+        if let Err(e) = content.write_chunk(&buffer[..read]).await {
+            content.discard().await;
+            return Err(e);
+        }
+    }
+    files_model::set_legacy_content(pg_pool, storage, file_id, name, content).await?;
+    storage.delete(&key).await
+}
@@ -1,23 +1,56 @@
+mod blob;
 mod file;
+mod file_type;
+mod file_version;
 mod folder;
+mod link;
+mod listing;
+mod plan;
+mod reservation;
+mod share;
+mod temp_file;
+mod thumbnail;
+mod upload_session;
 mod user;
 
+pub mod blobs_model;
 pub mod files_model;
 pub mod folders_model;
+pub mod links_model;
+pub mod plans_model;
+pub mod reservations_model;
 pub mod sessions_model;
+pub mod shares_model;
+pub mod thumbnails_model;
+pub mod trash_model;
+pub mod upload_sessions_model;
 pub mod users_model;
+pub mod versions_model;
 
+use crate::storage::Storage;
 use bb8_redis::{
     bb8::{self, Pool},
     RedisConnectionManager,
 };
 use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::time::Duration;
 use tokio::fs;
 
-pub use user::User;
+pub use blob::{Blob, WrongSize};
+pub use file::File;
+pub use file_type::{decode_text, detect_language};
+pub use folder::Folder;
+pub use link::Link;
+pub use listing::{Position, Sort, SortKey};
+pub use plan::Plan;
+pub use reservation::Reservation;
+pub use temp_file::TempFile;
+pub use upload_session::SessionLocks;
+pub use user::{User, WrongUsage};
 pub type RedisPool = Pool<RedisConnectionManager>;
 
 pub const FILES_FOLDER: &str = "files_data";
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 pub async fn init_postgres(url: &str, max_connections: u32) -> PgPool {
     // Create a connection pool
@@ -25,11 +58,12 @@ pub async fn init_postgres(url: &str, max_connections: u32) -> PgPool {
         .max_connections(max_connections)
         .connect(url)
         .await
-        .expect(&format!("Failed to connect to {url}"))
+        .unwrap_or_else(|_| panic!("Failed to connect to {url}"))
 }
 
 pub async fn init_redis(url: &str) -> RedisPool {
-    let manager = RedisConnectionManager::new(url).expect(&format!("Failed to connect to {url}"));
+    let manager =
+        RedisConnectionManager::new(url).unwrap_or_else(|_| panic!("Failed to connect to {url}"));
     bb8::Pool::builder()
         .build(manager)
         .await
@@ -39,5 +73,43 @@ pub async fn init_redis(url: &str) -> RedisPool {
 pub async fn init_files_folder() {
     fs::create_dir_all(FILES_FOLDER)
         .await
-        .expect(&format!("Failed to create '{}' folder", FILES_FOLDER));
+        .unwrap_or_else(|_| panic!("Failed to create '{}' folder", FILES_FOLDER));
+}
+
+/// Starts the periodic cleanup: what's left behind by interrupted operations, what expired and
+/// the items that have been in the trash for too long.
+pub fn init_maintenance(pg_pool: PgPool, storage: Storage, retention_days: i32) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+        loop {
+            interval.tick().await;
+            // The content left behind by operations that were interrupted
+            match blobs_model::purge_unused_blobs(&pg_pool, storage.as_ref()).await {
+                Ok(0) => {}
+                Ok(count) => println!("Purged {} unused blobs", count),
+                Err(e) => eprintln!("{}", e),
+            }
+            match reservations_model::purge_expired(&pg_pool).await {
+                Ok(0) => {}
+                Ok(count) => println!("Purged {} expired storage reservations", count),
+                Err(e) => eprintln!("{}", e),
+            }
+            match upload_sessions_model::purge_expired(&pg_pool).await {
+                Ok(0) => {}
+                Ok(count) => println!("Purged {} abandoned upload sessions", count),
+                Err(e) => eprintln!("{}", e),
+            }
+            // The counts of used bytes are kept up to date by the database, this is a safety net
+            match users_model::reconcile_used_storage(&pg_pool).await {
+                Ok(0) => {}
+                Ok(count) => println!("Fixed the used storage of {} users", count),
+                Err(e) => eprintln!("{}", e),
+            }
+            match trash_model::purge_expired(&pg_pool, storage.as_ref(), retention_days).await {
+                Ok(0) => {}
+                Ok(bytes) => println!("Purged {} bytes from the trash", bytes),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    });
 }
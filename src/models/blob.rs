@@ -0,0 +1,63 @@
+/// A stored content, with the number of files and versions that actually reference it.
+#[derive(sqlx::FromRow)]
+pub struct Blob {
+    pub(super) hash: String,
+    pub(super) size: i64,
+    pub(super) ref_count: i32,
+    pub(super) references: i64,
+    pub(super) broken: bool,
+}
+
+impl Blob {
+    pub fn get_hash(&self) -> &String {
+        &self.hash
+    }
+
+    pub fn get_size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn get_ref_count(&self) -> i32 {
+        self.ref_count
+    }
+
+    pub fn get_references(&self) -> i64 {
+        self.references
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+}
+
+/// A file, or a version of a file, whose size isn't the size of its content.
+#[derive(sqlx::FromRow)]
+pub struct WrongSize {
+    pub(super) file_id: i32,
+    pub(super) version_id: Option<i32>,
+    pub(super) name: String,
+    pub(super) size: i64,
+    pub(super) blob_size: i64,
+}
+
+impl WrongSize {
+    pub fn get_file_id(&self) -> i32 {
+        self.file_id
+    }
+
+    pub fn get_version_id(&self) -> Option<i32> {
+        self.version_id
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn get_blob_size(&self) -> i64 {
+        self.blob_size
+    }
+}
@@ -0,0 +1,378 @@
+use super::{thumbnails_model, Blob, TempFile, WrongSize};
+use crate::{errors::InternalError, storage::StorageBackend};
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+
+/// How long a blob is kept for content that is being written, if it's never referenced.
+const RESERVATION_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Returns the space taken on disk by the content of the user's files,
+/// counting only once the content shared by several files.
+pub async fn get_physical_usage(
+    conn: &mut PgConnection,
+    owner_id: i32,
+) -> Result<i64, InternalError> {
+    sqlx::query!(
+        r#"SELECT COALESCE(SUM(size), 0)::bigint as "size!"
+        FROM blobs
+        WHERE hash IN (SELECT fk_blob FROM files WHERE fk_owner = $1)
+        OR hash IN (SELECT fk_blob FROM file_versions
+            WHERE fk_file IN (SELECT id FROM files WHERE fk_owner = $1));"#,
+        owner_id
+    )
+    .fetch_one(conn)
+    .await
+    .map(|r| r.size)
+    .map_err(|_| InternalError("Failed to get the physical usage".to_string()))
+}
+
+/// Returns all the blobs, counting the files and versions that reference each of them.
+pub async fn get_blobs(pg_pool: &PgPool) -> Result<Vec<Blob>, InternalError> {
+    sqlx::query_as!(
+        Blob,
+        r#"SELECT b.hash, b.size, b.ref_count, b.broken,
+        (SELECT COUNT(*) FROM files WHERE fk_blob = b.hash)
+        + (SELECT COUNT(*) FROM file_versions WHERE fk_blob = b.hash) as "references!"
+        FROM blobs b
+        ORDER BY b.hash;"#
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the blobs".to_string()))
+}
+
+/// Returns the files and versions whose size isn't the size of their (healthy) blob.
+pub async fn get_wrong_sizes(pg_pool: &PgPool) -> Result<Vec<WrongSize>, InternalError> {
+    sqlx::query_as!(
+        WrongSize,
+        r#"SELECT f.id as "file_id!", NULL::integer as version_id, f.name as "name!",
+        f.size as "size!", b.size as "blob_size!"
+        FROM files f
+        JOIN blobs b ON b.hash = f.fk_blob
+        WHERE f.size <> b.size AND NOT b.broken
+        UNION ALL
+        SELECT f.id, v.id, f.name, v.size, b.size
+        FROM file_versions v
+        JOIN files f ON f.id = v.fk_file
+        JOIN blobs b ON b.hash = v.fk_blob
+        WHERE v.size <> b.size AND NOT b.broken
+        ORDER BY 1, 2;"#
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the sizes of the files".to_string()))
+}
+
+pub async fn set_blob_broken(
+    pg_pool: &PgPool,
+    hash: &str,
+    broken: bool,
+) -> Result<(), InternalError> {
+    sqlx::query!(
+        "UPDATE blobs
+        SET broken = $2
+        WHERE hash = $1;",
+        hash,
+        broken
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError(format!("Failed to mark blob '{}'", hash)))?;
+    Ok(())
+}
+
+/// Sets the number of references of the blob to the number of files and versions using it.
+pub async fn fix_ref_count(pg_pool: &PgPool, hash: &str) -> Result<(), InternalError> {
+    sqlx::query!(
+        "UPDATE blobs
+        SET ref_count = (SELECT COUNT(*) FROM files WHERE fk_blob = $1)
+            + (SELECT COUNT(*) FROM file_versions WHERE fk_blob = $1)
+        WHERE hash = $1;",
+        hash
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError(format!("Failed to fix the references of blob '{}'", hash)))?;
+    Ok(())
+}
+
+/// Sets the size of the files and versions to the size of their (healthy) blob.
+pub async fn fix_sizes(pg_pool: &PgPool) -> Result<(), InternalError> {
+    let err = |_| InternalError("Failed to fix the sizes of the files".to_string());
+    let mut tx = pg_pool.begin().await.map_err(err)?;
+    sqlx::query!(
+        "UPDATE files f
+        SET size = b.size
+        FROM blobs b
+        WHERE b.hash = f.fk_blob AND f.size <> b.size AND NOT b.broken;"
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(err)?;
+    sqlx::query!(
+        "UPDATE file_versions v
+        SET size = b.size
+        FROM blobs b
+        WHERE b.hash = v.fk_blob AND v.size <> b.size AND NOT b.broken;"
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(err)?;
+    tx.commit().await.map_err(err)
+}
+
+/// Stores the content in the blob with the same hash, creating it if needed, and takes a
+/// reference to it as part of a transaction. Returns the transaction, which must be committed
+/// for the reference to count, and the hash of the blob.
+///
+/// If the transaction isn't committed, `purge_blobs` must be called with the hash afterwards
+/// (what it misses is deleted by `purge_unused_blobs` eventually).
+pub(super) async fn store_blob(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    content: TempFile,
+) -> Result<(Transaction<'static, Postgres>, String), InternalError> {
+    let hash = match content.hash().await {
+        Ok(hash) => hash,
+        Err(e) => {
+            content.discard().await;
+            return Err(e);
+        }
+    };
+    let err = || InternalError(format!("Failed to store blob '{}'", hash));
+    // Nothing stays locked while the content is written, the reservation is enough to keep
+    // the blob from being purged
+    let reservation_id = match reserve_blob(pg_pool, &hash, content.get_size()).await {
+        Ok(id) => id,
+        Err(_) => {
+            content.discard().await;
+            return Err(err());
+        }
+    };
+    // Identical content may already be there, replacing it doesn't change anything
+    let res = storage.put(&hash, content).await;
+    let tx = match res {
+        Ok(_) => take_reference(pg_pool, &hash).await.map_err(|_| err()),
+        Err(e) => Err(e),
+    };
+    // Once the reference is taken the blob is locked until the transaction ends, so it can't
+    // be purged without the reservation either
+    let released = sqlx::query!(
+        "DELETE FROM blob_reservations
+        WHERE id = $1;",
+        reservation_id
+    )
+    .execute(pg_pool)
+    .await;
+    let e = match (tx, released) {
+        (Ok(tx), Ok(_)) => return Ok((tx, hash)),
+        (Ok(tx), Err(_)) => {
+            let _ = tx.rollback().await;
+            err()
+        }
+        (Err(e), _) => e,
+    };
+    let _ = purge_blobs(pg_pool, storage, &[hash]).await;
+    Err(e)
+}
+
+/// Takes one more reference to the blob of a file.
+pub(super) async fn retain_file_blob(
+    conn: &mut PgConnection,
+    file_id: i32,
+) -> Result<(), InternalError> {
+    sqlx::query!(
+        "UPDATE blobs
+        SET ref_count = ref_count + 1
+        WHERE hash = (SELECT fk_blob FROM files WHERE id = $1);",
+        file_id
+    )
+    .execute(conn)
+    .await
+    .map_err(|_| InternalError(format!("Failed to retain the blob of file {}", file_id)))?;
+    Ok(())
+}
+
+/// Takes one more reference to the blobs of the files in the folders, once per file.
+pub(super) async fn retain_folder_blobs(
+    conn: &mut PgConnection,
+    folder_ids: &[i32],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE blobs
+        SET ref_count = ref_count + c.count
+        FROM (
+            SELECT fk_blob, COUNT(*)::integer as count
+            FROM files
+            WHERE fk_parent = ANY($1)
+            GROUP BY fk_blob
+        ) c
+        WHERE hash = c.fk_blob;",
+        folder_ids
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Drops a reference to each of the blobs, as many times as they are in the list.
+/// Once the transaction is committed, the blobs must be given to `purge_blobs` so that the ones
+/// that aren't referenced anymore get deleted.
+pub(super) async fn release_blobs(
+    conn: &mut PgConnection,
+    hashes: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE blobs
+        SET ref_count = ref_count - c.count
+        FROM (
+            SELECT h, COUNT(*)::integer as count
+            FROM unnest($1::text[]) h
+            GROUP BY h
+        ) c
+        WHERE hash = c.h;",
+        hashes
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Deletes the blobs of the list that aren't referenced anymore, along with their content.
+pub(super) async fn purge_blobs(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    hashes: &[String],
+) -> Result<(), InternalError> {
+    let mut hashes = hashes.to_vec();
+    hashes.sort_unstable();
+    hashes.dedup();
+    for hash in hashes {
+        purge_blob(pg_pool, storage, &hash).await?;
+    }
+    Ok(())
+}
+
+/// Deletes all the blobs that aren't referenced anymore, including the ones left behind by
+/// operations that were interrupted. Returns the number of blobs that were deleted.
+pub async fn purge_unused_blobs(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+) -> Result<usize, InternalError> {
+    sqlx::query!(
+        "DELETE FROM blob_reservations
+        WHERE expires_at <= CURRENT_TIMESTAMP;"
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to purge the blob reservations".to_string()))?;
+    let hashes: Vec<String> = sqlx::query!(
+        "SELECT hash
+        FROM blobs
+        WHERE ref_count = 0
+        AND NOT EXISTS (SELECT 1 FROM blob_reservations WHERE fk_blob = hash);"
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the unused blobs".to_string()))?
+    .into_iter()
+    .map(|r| r.hash)
+    .collect();
+    purge_blobs(pg_pool, storage, &hashes).await?;
+    Ok(hashes.len())
+}
+
+/// Records the blob if it's new, and reserves it until its content is written.
+/// Returns the id of the reservation.
+async fn reserve_blob(pg_pool: &PgPool, hash: &str, size: i64) -> Result<i32, sqlx::Error> {
+    let mut tx = pg_pool.begin().await?;
+    // The blob is committed before its content is written, so that content can't exist
+    // without a blob. Updating the row waits for it to be purged if it's being purged,
+    // in which case it's recorded again.
+    sqlx::query!(
+        "INSERT INTO blobs (hash, size, ref_count)
+        VALUES ($1, $2, 0)
+        ON CONFLICT (hash) DO UPDATE
+        SET hash = EXCLUDED.hash;",
+        hash,
+        size
+    )
+    .execute(&mut *tx)
+    .await?;
+    let reservation = sqlx::query!(
+        "INSERT INTO blob_reservations (fk_blob, expires_at)
+        VALUES ($1, CURRENT_TIMESTAMP + make_interval(secs => $2))
+        RETURNING id;",
+        hash,
+        RESERVATION_TTL_SECS as f64
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(reservation.id)
+}
+
+/// Takes a reference to the blob in a transaction that keeps it locked.
+async fn take_reference(
+    pg_pool: &PgPool,
+    hash: &str,
+) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+    let mut tx = pg_pool.begin().await?;
+    sqlx::query!(
+        "UPDATE blobs
+        SET ref_count = ref_count + 1
+        WHERE hash = $1;",
+        hash
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(tx)
+}
+
+async fn purge_blob(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    hash: &str,
+) -> Result<(), InternalError> {
+    let err = || InternalError(format!("Failed to purge blob '{}'", hash));
+    let mut tx = pg_pool.begin().await.map_err(|_| err())?;
+    // The row stays locked until the commit, so the blob can't be stored again before
+    // the content is deleted
+    let unused = sqlx::query!(
+        "SELECT hash
+        FROM blobs
+        WHERE hash = $1 AND ref_count = 0
+        FOR UPDATE;",
+        hash
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| err())?;
+    if unused.is_none() {
+        return Ok(());
+    }
+    // Checked once the row is locked, so that the reservations made until then are seen
+    let reserved = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM blob_reservations WHERE fk_blob = $1) as "reserved!";"#,
+        hash
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| err())?
+    .reserved;
+    if reserved {
+        return Ok(());
+    }
+    // The blob is only deleted once its content is gone, so that it can be purged again if
+    // that doesn't go through
+    thumbnails_model::delete_thumbnails(&mut tx, storage, hash).await?;
+    storage.delete(hash).await?;
+    sqlx::query!(
+        "DELETE FROM blobs
+        WHERE hash = $1;",
+        hash
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| err())?;
+    tx.commit().await.map_err(|_| err())
+}
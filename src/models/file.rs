@@ -1,15 +1,19 @@
 use sqlx::types::chrono::NaiveDateTime;
 
+/// The queries list the columns instead of using `*`, since they are decoded in order and
+/// databases migrated from the first version have them in another order.
 #[derive(sqlx::FromRow)]
 pub struct File {
     pub(super) id: i32,
     pub(super) name: String,
     pub(super) file_type: Option<String>,
-    pub(super) size: i32,
+    pub(super) mime_type: String,
+    pub(super) size: i64,
     pub(super) last_modified: NaiveDateTime,
     pub(super) starred: bool,
     pub(super) fk_owner: i32,
     pub(super) fk_parent: i32,
+    pub(super) fk_blob: String,
 }
 
 impl File {
@@ -25,7 +29,11 @@ impl File {
         &self.file_type
     }
 
-    pub fn get_size(&self) -> i32 {
+    pub fn get_mime_type(&self) -> &String {
+        &self.mime_type
+    }
+
+    pub fn get_size(&self) -> i64 {
         self.size
     }
 
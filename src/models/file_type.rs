@@ -0,0 +1,327 @@
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use std::path::Path;
+
+/// How much of the beginning of the content is looked at to detect its type.
+pub const SNIFF_LENGTH: usize = 8 * 1024;
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+// Text with more control characters than this (per character) is considered binary
+const MAX_CONTROL_RATIO: usize = 10;
+
+/// Returns the MIME type of the content and its category ("Text", "Image", ...), if it has one.
+/// The type is detected from the first bytes of the content, and from the extension of the
+/// file name when the content doesn't tell.
+pub fn detect_file_type(file_name: &str, head: &[u8]) -> (String, Option<String>) {
+    let by_extension = Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(|e| mime_from_extension(&e.to_lowercase()));
+    let mime_type = match (sniff(head), by_extension) {
+        // Office documents, e-books and the like are generic containers
+        (Some(sniffed), Some(ext)) if is_contained_in(ext, sniffed) => ext,
+        (Some(sniffed), _) => sniffed,
+        // Text can't be told apart by its content, but the extension can say what kind it is,
+        // SVG images included
+        (None, Some(ext)) if looks_like_text(head) == (is_text(ext) || ext == "image/svg+xml") => {
+            ext
+        }
+        (None, _) if !head.is_empty() && looks_like_text(head) => "text/plain",
+        (None, Some(ext)) => ext,
+        (None, None) => DEFAULT_MIME_TYPE,
+    };
+    (
+        mime_type.to_string(),
+        category(mime_type).map(str::to_string),
+    )
+}
+
+/// Decodes the beginning of a text file, returning the text and the name of its encoding.
+/// The encoding is given by the byte order mark, or is UTF-8 when the content is valid UTF-8,
+/// or Windows-1252 otherwise. Returns None if the content looks binary.
+pub fn decode_text(head: &[u8], truncated: bool) -> Option<(String, &'static str)> {
+    let (encoding, bom_length) = match Encoding::for_bom(head) {
+        Some(found) => found,
+        None if looks_like_text(head) => (UTF_8, 0),
+        None => (WINDOWS_1252, 0),
+    };
+    let (text, _) = encoding.decode_without_bom_handling(&head[bom_length..]);
+    // The head may end in the middle of a character
+    let text = if truncated {
+        text.trim_end_matches('\u{FFFD}')
+    } else {
+        &text
+    };
+    let control = text
+        .chars()
+        .filter(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0C' | '\x1B'))
+        .count();
+    if text.contains('\0') || control * MAX_CONTROL_RATIO > text.chars().count() {
+        return None;
+    }
+    Some((text.to_string(), encoding.name()))
+}
+
+/// Returns the programming (or markup) language of the file, given by its extension.
+pub fn detect_language(file_name: &str) -> Option<&'static str> {
+    let extension = Path::new(file_name).extension()?.to_str()?.to_lowercase();
+    let language = match extension.as_str() {
+        "c" | "h" => "c",
+        "cpp" | "hpp" => "cpp",
+        "cs" => "csharp",
+        "go" => "go",
+        "java" => "java",
+        "kt" => "kotlin",
+        "scala" => "scala",
+        "swift" => "swift",
+        "rs" => "rust",
+        "nim" => "nim",
+        "py" => "python",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "pl" => "perl",
+        "js" | "mjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "svelte" => "svelte",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "xml" | "svg" => "xml",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "ini" | "config" => "ini",
+        "md" => "markdown",
+        "csv" => "csv",
+        "sql" => "sql",
+        "sh" => "bash",
+        "bat" => "batch",
+        "ps1" => "powershell",
+        "asm" => "asm",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Returns the category of the MIME type, like "Image" for "image/png".
+fn category(mime_type: &str) -> Option<&'static str> {
+    if is_text(mime_type) {
+        return Some("Text");
+    }
+    let category = match mime_type.split_once('/')?.0 {
+        "image" => "Image",
+        "audio" => "Audio",
+        "video" => "Video",
+        _ => match mime_type {
+            "application/pdf"
+            | "application/rtf"
+            | "application/epub+zip"
+            | "application/msword"
+            | "application/vnd.ms-excel"
+            | "application/vnd.ms-powerpoint"
+            | "application/vnd.oasis.opendocument.text"
+            | "application/vnd.oasis.opendocument.spreadsheet"
+            | "application/vnd.oasis.opendocument.presentation"
+            | "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            | "application/vnd.openxmlformats-officedocument.presentationml.presentation" => {
+                "Document"
+            }
+            "application/zip"
+            | "application/gzip"
+            | "application/x-tar"
+            | "application/x-bzip2"
+            | "application/x-xz"
+            | "application/zstd"
+            | "application/x-7z-compressed"
+            | "application/vnd.rar" => "Archive",
+            _ => return None,
+        },
+    };
+    Some(category)
+}
+
+fn is_text(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || matches!(
+            mime_type,
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/sql"
+                | "application/toml"
+                | "application/yaml"
+                | "application/x-sh"
+        )
+}
+
+/// Whether content of the MIME type is stored in the container format.
+fn is_contained_in(mime_type: &str, container: &str) -> bool {
+    match container {
+        "application/zip" => {
+            mime_type == "application/epub+zip"
+                || mime_type == "application/java-archive"
+                || mime_type.starts_with("application/vnd.oasis.opendocument.")
+                || mime_type.starts_with("application/vnd.openxmlformats-officedocument.")
+        }
+        "application/x-ole-storage" => matches!(
+            mime_type,
+            "application/msword" | "application/vnd.ms-excel" | "application/vnd.ms-powerpoint"
+        ),
+        _ => false,
+    }
+}
+
+/// Text is valid UTF-8 (or starts with a UTF-16 byte order mark) and has no NUL bytes.
+fn looks_like_text(head: &[u8]) -> bool {
+    if head.starts_with(&[0xFF, 0xFE]) || head.starts_with(&[0xFE, 0xFF]) {
+        return true;
+    }
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // The head may end in the middle of a character
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// Returns the MIME type given by the magic bytes at the beginning of the content.
+fn sniff(head: &[u8]) -> Option<&'static str> {
+    let starts = |magic: &[u8]| head.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| head.get(offset..).is_some_and(|h| h.starts_with(magic));
+    let mime_type = if starts(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if starts(b"\xFF\xD8\xFF") {
+        "image/jpeg"
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        "image/gif"
+    } else if starts(b"RIFF") && at(8, b"WEBP") {
+        "image/webp"
+    } else if starts(b"BM") && matches!(head.get(14..18), Some([12 | 40 | 56 | 108 | 124, 0, 0, 0]))
+    {
+        "image/bmp"
+    } else if starts(b"II*\0") || starts(b"MM\0*") {
+        "image/tiff"
+    } else if starts(b"\0\0\x01\0") {
+        "image/x-icon"
+    } else if at(4, b"ftyp") {
+        // The brand says what's in the ISO media container
+        match head.get(8..12)? {
+            b"heic" | b"heix" | b"mif1" | b"msf1" => "image/heif",
+            b"avif" => "image/avif",
+            b"M4A " => "audio/mp4",
+            b"qt  " => "video/quicktime",
+            _ => "video/mp4",
+        }
+    } else if starts(b"\x1A\x45\xDF\xA3") {
+        "video/webm"
+    } else if starts(b"RIFF") && at(8, b"AVI ") {
+        "video/x-msvideo"
+    } else if starts(b"ID3") || starts(b"\xFF\xFB") || starts(b"\xFF\xF3") || starts(b"\xFF\xF2") {
+        "audio/mpeg"
+    } else if starts(b"RIFF") && at(8, b"WAVE") {
+        "audio/wav"
+    } else if starts(b"fLaC") {
+        "audio/flac"
+    } else if starts(b"OggS") {
+        "audio/ogg"
+    } else if starts(b"%PDF-") {
+        "application/pdf"
+    } else if starts(b"{\\rtf") {
+        "application/rtf"
+    } else if starts(b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1") {
+        // The old Office formats, the extension says which one
+        "application/x-ole-storage"
+    } else if starts(b"PK\x03\x04") || starts(b"PK\x05\x06") {
+        "application/zip"
+    } else if starts(b"\x1F\x8B") {
+        "application/gzip"
+    } else if at(257, b"ustar") {
+        "application/x-tar"
+    } else if starts(b"BZh") && head.get(3).is_some_and(|b| (b'1'..=b'9').contains(b)) {
+        "application/x-bzip2"
+    } else if starts(b"\xFD7zXZ\0") {
+        "application/x-xz"
+    } else if starts(b"\x28\xB5\x2F\xFD") {
+        "application/zstd"
+    } else if starts(b"7z\xBC\xAF\x27\x1C") {
+        "application/x-7z-compressed"
+    } else if starts(b"Rar!\x1A\x07") {
+        "application/vnd.rar"
+    } else if starts(b"\0asm") {
+        "application/wasm"
+    } else if starts(b"\x7FELF") {
+        "application/x-executable"
+    } else if starts(b"SQLite format 3\0") {
+        "application/vnd.sqlite3"
+    } else {
+        return None;
+    };
+    Some(mime_type)
+}
+
+fn mime_from_extension(extension: &str) -> Option<&'static str> {
+    let mime_type = match extension {
+        "txt" | "c" | "cpp" | "h" | "hpp" | "py" | "rs" | "svelte" | "jsx" | "nim" | "go"
+        | "java" | "php" | "rb" | "lua" | "swift" | "kt" | "scala" | "pl" | "bat" | "ps1"
+        | "ts" | "tsx" | "asm" | "config" | "env" | "gitignore" | "cs" | "log" | "ini" => {
+            "text/plain"
+        }
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "md" => "text/markdown",
+        "js" | "mjs" => "application/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/yaml",
+        "toml" => "application/toml",
+        "sql" => "application/sql",
+        "sh" => "application/x-sh",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "webp" => "image/webp",
+        "tiff" | "tif" => "image/tiff",
+        "heif" | "heic" => "image/heif",
+        "avif" => "image/avif",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "ogg" | "oga" => "audio/ogg",
+        "m4a" => "audio/mp4",
+        "mp4" | "m4v" => "video/mp4",
+        "mov" => "video/quicktime",
+        "webm" | "mkv" => "video/webm",
+        "avi" => "video/x-msvideo",
+        "pdf" => "application/pdf",
+        "rtf" => "application/rtf",
+        "epub" => "application/epub+zip",
+        "doc" => "application/msword",
+        "xls" => "application/vnd.ms-excel",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "odt" => "application/vnd.oasis.opendocument.text",
+        "ods" => "application/vnd.oasis.opendocument.spreadsheet",
+        "odp" => "application/vnd.oasis.opendocument.presentation",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "jar" => "application/java-archive",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "bz2" => "application/x-bzip2",
+        "xz" => "application/x-xz",
+        "zst" => "application/zstd",
+        "7z" => "application/x-7z-compressed",
+        "rar" => "application/vnd.rar",
+        "wasm" => "application/wasm",
+        _ => return None,
+    };
+    Some(mime_type)
+}
@@ -0,0 +1,34 @@
+use sqlx::types::chrono::NaiveDateTime;
+
+#[derive(sqlx::FromRow)]
+pub struct FileVersion {
+    pub(super) id: i32,
+    pub(super) file_type: Option<String>,
+    pub(super) mime_type: String,
+    pub(super) size: i64,
+    pub(super) last_modified: NaiveDateTime,
+    pub(super) fk_file: i32,
+    pub(super) fk_blob: String,
+}
+
+impl FileVersion {
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn get_mime_type(&self) -> &String {
+        &self.mime_type
+    }
+
+    pub fn get_size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn get_last_modified(&self) -> &NaiveDateTime {
+        &self.last_modified
+    }
+
+    pub fn get_fk_file(&self) -> i32 {
+        self.fk_file
+    }
+}
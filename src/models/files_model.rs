@@ -1,60 +1,210 @@
 use super::file::File;
-use super::FILES_FOLDER;
-use crate::errors::{FileError, InternalError};
-use axum::body::Bytes;
-use sqlx::PgPool;
-use std::path::{Path, PathBuf};
-use tokio::{fs, io::AsyncWriteExt};
+use super::file_type::{detect_file_type, SNIFF_LENGTH};
+use super::{
+    blobs_model, folders_model, reservations_model, versions_model, Position, Reservation, Sort,
+    TempFile,
+};
+use crate::{
+    errors::{FileError, InternalError},
+    storage::{ContentReader, StorageBackend},
+};
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use tokio::io::AsyncReadExt;
 
 pub async fn new_file(
     pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
     file_name: &str,
-    content: &Bytes,
+    content: TempFile,
     parent_folder_id: i32,
     owner_id: i32,
+    reservation: &Reservation,
 ) -> Result<File, FileError> {
-    if !validate_name(file_name) {
-        return Err(FileError::NameError);
-    }
-    let file_type = get_file_type(file_name);
-    let file_size = content.len() as i32;
-    let file = sqlx::query_as!(
-        File,
-        "INSERT INTO files (name, file_type, size, last_modified, starred, fk_owner, fk_parent)
-        VALUES ($1, $2, $3, CURRENT_TIMESTAMP, $4, $5, $6)
-        RETURNING *;",
+    let (tx, file, hash) = prepare_file(
+        pg_pool,
+        storage,
         file_name,
-        file_type,
-        file_size,
-        false,
+        content,
+        parent_folder_id,
         owner_id,
-        parent_folder_id
+        reservation,
     )
-    .fetch_one(pg_pool)
-    .await
-    .map_err(|_| FileError::InternalError)?;
-    save_file_content(file.id, content)
+    .await?;
+    if tx.commit().await.is_err() {
+        // The content isn't referenced if it's new
+        let _ = blobs_model::purge_blobs(pg_pool, storage, &[hash]).await;
+        return Err(FileError::InternalError);
+    }
+    Ok(file)
+}
+
+/// Stores the content and adds the file in a transaction, which is returned with the file and
+/// the hash of the content so that more can be done before it's committed.
+///
+/// If the transaction isn't committed, `blobs_model::purge_blobs` must be called with the hash
+/// afterwards.
+pub(super) async fn prepare_file(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    file_name: &str,
+    content: TempFile,
+    parent_folder_id: i32,
+    owner_id: i32,
+    reservation: &Reservation,
+) -> Result<(Transaction<'static, Postgres>, File, String), FileError> {
+    // Shared folders are read-only, so the parent must be one of the user's folders
+    let error = match folders_model::find_folder(pg_pool, parent_folder_id, owner_id).await {
+        _ if !validate_name(file_name) => Some(FileError::NameError),
+        Ok(Some(_)) => None,
+        Ok(None) => Some(FileError::NotFound),
+        Err(_) => Some(FileError::InternalError),
+    };
+    if let Some(e) = error {
+        content.discard().await;
+        return Err(e);
+    }
+    let head = match content.read_head(SNIFF_LENGTH).await {
+        Ok(head) => head,
+        Err(_) => {
+            content.discard().await;
+            return Err(FileError::InternalError);
+        }
+    };
+    let (mime_type, file_type) = detect_file_type(file_name, &head);
+    let file_size = content.get_size();
+    // The row is saved in the same transaction as the reference to the content, identical
+    // content is shared with the existing files
+    let (mut tx, hash) = blobs_model::store_blob(pg_pool, storage, content)
         .await
         .map_err(|_| FileError::InternalError)?;
-    Ok(file)
+    let res = save_file_row(
+        &mut tx,
+        file_name,
+        (&mime_type, file_type),
+        file_size,
+        parent_folder_id,
+        owner_id,
+        &hash,
+    )
+    .await;
+    // The file takes the place of the storage that was reserved for it
+    let res = match res {
+        Ok(file) => reservations_model::use_reservation(&mut tx, reservation)
+            .await
+            .map(|_| file),
+        Err(e) => Err(e),
+    };
+    match res {
+        Ok(file) => Ok((tx, file, hash)),
+        Err(_) => {
+            let _ = tx.rollback().await;
+            // The content isn't referenced if it's new
+            let _ = blobs_model::purge_blobs(pg_pool, storage, &[hash]).await;
+            Err(FileError::InternalError)
+        }
+    }
 }
 
+/// Returns the files of the folder in the given order, starting after the position if there's
+/// one. All of them are returned when there's no limit.
 pub async fn get_files(
     pg_pool: &PgPool,
     parent_folder_id: i32,
+    user_id: i32,
+    sort: Sort,
+    after: Option<&Position>,
+    limit: Option<i64>,
+) -> Result<Vec<File>, InternalError> {
+    let (condition, order) = sort.to_sql(false, 3);
+    // The files can be read by their owner or by whoever the folder is shared with
+    let sql = format!(
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
+        FROM files
+        WHERE fk_parent = $2 AND (fk_owner = $1 OR has_folder_read_access($2, $1))
+        AND ($6 OR {condition})
+        ORDER BY {order}
+        LIMIT $7;"
+    );
+    sqlx::query_as::<_, File>(&sql)
+        .bind(user_id)
+        .bind(parent_folder_id)
+        .bind(after.map(|p| p.value.as_str()))
+        .bind(after.map(|p| p.name.as_str()))
+        .bind(after.map(|p| p.id))
+        .bind(after.is_none())
+        .bind(limit)
+        .fetch_all(pg_pool)
+        .await
+        .map_err(|_| InternalError("Failed get the files from the database".to_string()))
+}
+
+/// What the files returned by `search_files` must match. Missing criteria match everything.
+pub struct FileSearch<'a> {
+    pub name: Option<&'a str>,
+    pub file_type: Option<&'a str>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    // Unix timestamps, in seconds
+    pub modified_after: Option<i64>,
+    pub modified_before: Option<i64>,
+    pub starred: Option<bool>,
+    // Only the files inside this folder (at any depth) are searched
+    pub folder_id: Option<i32>,
+}
+
+/// Searches the files of the user, outside of the trash unless a folder is given.
+/// Names containing the searched text come first, followed by similar names.
+pub async fn search_files(
+    pg_pool: &PgPool,
     owner_id: i32,
+    search: &FileSearch<'_>,
+    limit: i64,
+    offset: i64,
 ) -> Result<Vec<File>, InternalError> {
-    sqlx::query_as!(
+    let err = |_| InternalError("Failed to search the files".to_string());
+    let pattern = search.name.map(|n| format!("%{}%", escape_like(n)));
+    let mut tx = pg_pool.begin().await.map_err(err)?;
+    // The <% operator can use the trigram index, unlike word_similarity() with a fixed threshold
+    sqlx::query!("SELECT set_config('pg_trgm.word_similarity_threshold', '0.4', true);")
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(err)?;
+    let files = sqlx::query_as!(
         File,
-        "SELECT *
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
         FROM files
-        WHERE fk_owner = $1 AND fk_parent = $2;",
+        WHERE fk_owner = $1
+        AND ($2::integer IS null OR fk_parent IN (SELECT folder_id FROM get_folder_tree($2, $1)))
+        AND ($2::integer IS NOT null OR fk_parent NOT IN (SELECT folder_id FROM get_trash_tree($1)))
+        AND ($3::text IS null OR name ILIKE $4 OR $3 <% name)
+        AND ($5::text IS null OR file_type = $5)
+        AND ($6::bigint IS null OR size >= $6)
+        AND ($7::bigint IS null OR size <= $7)
+        AND ($8::bigint IS null OR last_modified >= to_timestamp($8)::timestamp)
+        AND ($9::bigint IS null OR last_modified <= to_timestamp($9)::timestamp)
+        AND ($10::boolean IS null OR starred = $10)
+        ORDER BY name ILIKE $4 DESC, word_similarity($3, name) DESC, name, id
+        LIMIT $11 OFFSET $12;",
         owner_id,
-        parent_folder_id
+        search.folder_id,
+        search.name,
+        pattern,
+        search.file_type,
+        search.min_size,
+        search.max_size,
+        search.modified_after,
+        search.modified_before,
+        search.starred,
+        limit,
+        offset
     )
-    .fetch_all(pg_pool)
+    .fetch_all(&mut *tx)
     .await
-    .map_err(|_| InternalError("Failed get the files from the database".to_string()))
+    .map_err(err)?;
+    tx.commit().await.map_err(err)?;
+    Ok(files)
 }
 
 pub async fn rename_file(
@@ -80,25 +230,90 @@ pub async fn rename_file(
     Ok(())
 }
 
-pub async fn get_file(
+/// Stars or unstars the file. Returns false if the user doesn't own such a file.
+pub async fn set_file_starred(
     pg_pool: &PgPool,
     file_id: i32,
     owner_id: i32,
-) -> Result<(String, Vec<u8>), InternalError> {
-    let name = sqlx::query!(
-        "SELECT name
-        FROM files
+    starred: bool,
+) -> Result<bool, InternalError> {
+    sqlx::query!(
+        "UPDATE files
+        SET starred = $3
         WHERE id = $1 AND fk_owner = $2;",
         file_id,
+        owner_id,
+        starred
+    )
+    .execute(pg_pool)
+    .await
+    .map(|r| r.rows_affected() > 0)
+    .map_err(|_| InternalError("Failed to star the file".to_string()))
+}
+
+/// Returns the starred files of the user, wherever they are, except in the trash.
+pub async fn get_starred_files(
+    pg_pool: &PgPool,
+    owner_id: i32,
+) -> Result<Vec<File>, InternalError> {
+    sqlx::query_as!(
+        File,
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
+        FROM files
+        WHERE fk_owner = $1 AND starred
+        AND fk_parent NOT IN (SELECT folder_id FROM get_trash_tree($1))
+        ORDER BY name;",
         owner_id
     )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the starred files".to_string()))
+}
+
+pub async fn get_file(pg_pool: &PgPool, file_id: i32, user_id: i32) -> Result<File, InternalError> {
+    // Shared files are read-only, but they can still be downloaded
+    sqlx::query_as!(
+        File,
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
+        FROM files
+        WHERE id = $1 AND (fk_owner = $2 OR has_file_read_access($1, $2));",
+        file_id,
+        user_id
+    )
     .fetch_one(pg_pool)
     .await
-    .map_err(|_| InternalError("Failed to get the file".to_string()))?
-    .name;
-    read_file_content(file_id)
+    .map_err(|_| InternalError("Failed to get the file".to_string()))
+}
+
+/// Reads the content of the file, or only the given (inclusive) range of bytes.
+pub async fn read_file_content(
+    storage: &dyn StorageBackend,
+    file: &File,
+    range: Option<(u64, u64)>,
+) -> Result<ContentReader, InternalError> {
+    storage.get(&file.fk_blob, range).await
+}
+
+/// Reads up to `len` bytes from the beginning of the content of the file.
+pub async fn read_file_head(
+    storage: &dyn StorageBackend,
+    file: &File,
+    len: u64,
+) -> Result<Vec<u8>, InternalError> {
+    let len = len.min(file.size as u64);
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    let mut head = Vec::with_capacity(len as usize);
+    storage
+        .get(&file.fk_blob, Some((0, len - 1)))
+        .await?
+        .read_to_end(&mut head)
         .await
-        .map(|content| (name, content))
+        .map_err(|_| InternalError("Failed to read the file".to_string()))?;
+    Ok(head)
 }
 
 pub async fn move_file(
@@ -123,45 +338,218 @@ pub async fn move_file(
 
 pub async fn delete_file(
     pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
     file_id: i32,
     owner_id: i32,
 ) -> Result<(), InternalError> {
-    sqlx::query!(
-        "DELETE FROM files
-        WHERE id = $1 AND fk_owner = $2;",
+    let err = || InternalError("Failed to delete the file".to_string());
+    let mut tx = pg_pool.begin().await.map_err(|_| err())?;
+    // The previous versions are deleted with the file
+    let blobs: Vec<String> = sqlx::query!(
+        r#"WITH deleted AS (
+            DELETE FROM files
+            WHERE id = $1 AND fk_owner = $2
+            RETURNING id, fk_blob
+        ), deleted_versions AS (
+            DELETE FROM file_versions
+            WHERE fk_file IN (SELECT id FROM deleted)
+            RETURNING fk_blob
+        )
+        SELECT fk_blob as "fk_blob!" FROM deleted
+        UNION ALL
+        SELECT fk_blob FROM deleted_versions;"#,
         file_id,
         owner_id
     )
-    .execute(pg_pool)
+    .fetch_all(&mut *tx)
     .await
-    .map_err(|_| InternalError("Failed to delete the file".to_string()))?;
-    delete_file_content(file_id).await?;
-    Ok(())
+    .map_err(|_| err())?
+    .into_iter()
+    .map(|r| r.fk_blob)
+    .collect();
+    blobs_model::release_blobs(&mut tx, &blobs)
+        .await
+        .map_err(|_| err())?;
+    tx.commit().await.map_err(|_| err())?;
+    // The content is deleted once nothing references it anymore
+    blobs_model::purge_blobs(pg_pool, storage, &blobs).await
 }
 
+/// Copies the file into the destination folder, or next to the original if none is given.
 pub async fn duplicate_file(
     pg_pool: &PgPool,
     file_id: i32,
     owner_id: i32,
+    to_folder_id: Option<i32>,
+    reservation: &Reservation,
 ) -> Result<File, InternalError> {
+    let mut tx = pg_pool
+        .begin()
+        .await
+        .map_err(|_| InternalError("Failed to duplicate the file".to_string()))?;
+    // The copy shares the content of the original file
     let file = sqlx::query_as!(
         File,
-        "INSERT INTO files (name, file_type, size, last_modified, starred, fk_owner, fk_parent)
-        SELECT name, file_type, size, CURRENT_TIMESTAMP, starred, fk_owner, fk_parent
+        "INSERT INTO files (name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob)
+        SELECT name, file_type, mime_type, size, CURRENT_TIMESTAMP, starred, fk_owner,
+        COALESCE($3, fk_parent), fk_blob
         FROM files
         WHERE id = $1 AND fk_owner = $2
-        RETURNING *;",
+        AND ($3::integer IS null OR fk_owner = (SELECT fk_owner FROM folders WHERE id = $3))
+        RETURNING id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob;",
         file_id,
-        owner_id
+        owner_id,
+        to_folder_id
     )
-    .fetch_one(pg_pool)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|_| InternalError("Failed to duplicate the file".to_string()))?;
-    let content = read_file_content(file_id).await?;
-    save_file_content(file.id, &Bytes::from(content)).await?;
+    blobs_model::retain_file_blob(&mut tx, file.id).await?;
+    reservations_model::use_reservation(&mut tx, reservation)
+        .await
+        .map_err(|_| InternalError("Failed to duplicate the file".to_string()))?;
+    tx.commit()
+        .await
+        .map_err(|_| InternalError("Failed to duplicate the file".to_string()))?;
     Ok(file)
 }
 
+/// Returns the number of files of the user and their size for each type of file.
+pub async fn get_usage_by_type(
+    pg_pool: &PgPool,
+    owner_id: i32,
+) -> Result<Vec<(Option<String>, i64, i64)>, InternalError> {
+    let rows = sqlx::query!(
+        r#"SELECT file_type, COUNT(*) as "files!", COALESCE(SUM(size), 0)::bigint as "size!"
+        FROM files
+        WHERE fk_owner = $1
+        GROUP BY file_type
+        ORDER BY 3 DESC;"#,
+        owner_id
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the usage by type".to_string()))?;
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.file_type, r.files, r.size))
+        .collect())
+}
+
+/// Returns the number of files of the user and their size, grouped by the smallest of the ages
+/// (in days) that they were last modified within. The age is None for the files older than all.
+pub async fn get_usage_by_age(
+    pg_pool: &PgPool,
+    owner_id: i32,
+    ages: &[i32],
+) -> Result<Vec<(Option<i32>, i64, i64)>, InternalError> {
+    let rows = sqlx::query!(
+        r#"SELECT (
+            SELECT MIN(d) FROM unnest($2::integer[]) d
+            WHERE last_modified > CURRENT_TIMESTAMP - make_interval(days => d)
+        ) as max_age, COUNT(*) as "files!", COALESCE(SUM(size), 0)::bigint as "size!"
+        FROM files
+        WHERE fk_owner = $1
+        GROUP BY 1
+        ORDER BY 1 NULLS LAST;"#,
+        owner_id,
+        ages
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the usage by age".to_string()))?;
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.max_age, r.files, r.size))
+        .collect())
+}
+
+pub async fn get_largest_files(
+    pg_pool: &PgPool,
+    owner_id: i32,
+    limit: i64,
+) -> Result<Vec<File>, InternalError> {
+    sqlx::query_as!(
+        File,
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
+        FROM files
+        WHERE fk_owner = $1
+        ORDER BY size DESC, id
+        LIMIT $2;",
+        owner_id,
+        limit
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the largest files".to_string()))
+}
+
+/// Returns the id and the name of the files whose content is still stored under their id, as it
+/// was before identical content was shared between files.
+pub async fn get_legacy_files(pg_pool: &PgPool) -> Result<Vec<(i32, String)>, InternalError> {
+    sqlx::query!(
+        "SELECT id, name
+        FROM files
+        WHERE fk_blob IS null
+        ORDER BY id;"
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map(|rows| rows.into_iter().map(|r| (r.id, r.name)).collect())
+    .map_err(|_| InternalError("Failed to get the files to migrate".to_string()))
+}
+
+/// Gives a file whose content was stored under its id the blob of the content, and detects its
+/// type again from the content.
+pub async fn set_legacy_content(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    file_id: i32,
+    file_name: &str,
+    content: TempFile,
+) -> Result<(), InternalError> {
+    let head = match content.read_head(SNIFF_LENGTH).await {
+        Ok(head) => head,
+        Err(e) => {
+            content.discard().await;
+            return Err(e);
+        }
+    };
+    let (mime_type, file_type) = detect_file_type(file_name, &head);
+    let file_size = content.get_size();
+    let (mut tx, hash) = blobs_model::store_blob(pg_pool, storage, content).await?;
+    let res = sqlx::query!(
+        "UPDATE files
+        SET fk_blob = $2, mime_type = $3, file_type = $4, size = $5
+        WHERE id = $1;",
+        file_id,
+        hash,
+        mime_type,
+        file_type,
+        file_size
+    )
+    .execute(&mut *tx)
+    .await;
+    let res = match res {
+        Ok(_) => tx.commit().await,
+        Err(e) => {
+            let _ = tx.rollback().await;
+            Err(e)
+        }
+    };
+    if res.is_err() {
+        let _ = blobs_model::purge_blobs(pg_pool, storage, &[hash]).await;
+        return Err(InternalError(format!(
+            "Failed to migrate the content of file {}",
+            file_id
+        )));
+    }
+    Ok(())
+}
+
 pub async fn get_file_by_id(
     pg_pool: &PgPool,
     file_id: i32,
@@ -169,7 +557,8 @@ pub async fn get_file_by_id(
 ) -> Result<File, InternalError> {
     sqlx::query_as!(
         File,
-        "SELECT *
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
         FROM files
         WHERE id = $1 AND fk_owner = $2;",
         file_id,
@@ -180,73 +569,147 @@ pub async fn get_file_by_id(
     .map_err(|_| InternalError("Failed to get the file".to_string()))
 }
 
-pub(super) async fn delete_user_files(
+/// Returns the file if it exists and belongs to the user.
+pub(super) async fn find_file(
     pg_pool: &PgPool,
+    file_id: i32,
     owner_id: i32,
-) -> Result<(), InternalError> {
-    let files_ids = sqlx::query!(
-        "DELETE FROM files
-        WHERE fk_owner = $1
-        RETURNING id;",
+) -> Result<Option<File>, sqlx::Error> {
+    sqlx::query_as!(
+        File,
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
+        FROM files
+        WHERE id = $1 AND fk_owner = $2;",
+        file_id,
         owner_id
     )
-    .fetch_all(pg_pool)
+    .fetch_optional(pg_pool)
     .await
-    .map_err(|_| InternalError("Failed to delete user files".to_string()))?;
-    for file_id in files_ids {
-        delete_file_content(file_id.id).await?;
-    }
-    Ok(())
 }
 
-pub(super) async fn save_file_content(file_id: i32, content: &Bytes) -> Result<(), InternalError> {
-    let path = build_file_path(file_id);
-    let data = content.to_vec();
-    let mut file = fs::File::create(path)
-        .await
-        .map_err(|_| InternalError(format!("Failed to create file '{}'", file_id)))?;
-    file.write_all(&data)
-        .await
-        .map_err(|_| InternalError(format!("Failed to write data to file '{}'", file_id)))?;
-    Ok(())
+pub(super) async fn get_tree_files(
+    pg_pool: &PgPool,
+    folder_id: i32,
+    owner_id: i32,
+) -> Result<Vec<File>, InternalError> {
+    sqlx::query_as!(
+        File,
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
+        FROM files
+        WHERE fk_parent IN (SELECT folder_id FROM get_folder_tree($1, $2));",
+        folder_id,
+        owner_id
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError(format!("Failed to get the files in folder {}", folder_id)))
 }
 
-pub(super) async fn read_file_content(file_id: i32) -> Result<Vec<u8>, InternalError> {
-    let path = build_file_path(file_id);
-    fs::read(path)
-        .await
-        .map_err(|_| InternalError(format!("Failed to read content from '{}'", file_id)))
+/// Deletes all the files of the user, returning the blobs they referenced once released.
+pub(super) async fn delete_user_files(
+    conn: &mut PgConnection,
+    owner_id: i32,
+) -> Result<Vec<String>, sqlx::Error> {
+    let blobs: Vec<String> = sqlx::query!(
+        r#"WITH deleted AS (
+            DELETE FROM files
+            WHERE fk_owner = $1
+            RETURNING id, fk_blob
+        ), deleted_versions AS (
+            DELETE FROM file_versions
+            WHERE fk_file IN (SELECT id FROM deleted)
+            RETURNING fk_blob
+        )
+        SELECT fk_blob as "fk_blob!" FROM deleted
+        UNION ALL
+        SELECT fk_blob FROM deleted_versions;"#,
+        owner_id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|r| r.fk_blob)
+    .collect();
+    blobs_model::release_blobs(conn, &blobs).await?;
+    Ok(blobs)
 }
 
-pub(super) async fn delete_file_content(file_id: i32) -> Result<(), InternalError> {
-    let path = build_file_path(file_id);
-    fs::remove_file(path)
-        .await
-        .map_err(|_| InternalError(format!("Failed to delete content for file '{}'", file_id)))
+/// Adds a file with the given content to the folder.
+/// If the folder already has a file with that name, its content is replaced and the previous
+/// content is kept as a version.
+async fn save_file_row(
+    conn: &mut PgConnection,
+    file_name: &str,
+    (mime_type, file_type): (&str, Option<String>),
+    file_size: i64,
+    parent_folder_id: i32,
+    owner_id: i32,
+    hash: &str,
+) -> Result<File, sqlx::Error> {
+    let existing = sqlx::query_as!(
+        File,
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
+        FROM files
+        WHERE fk_parent = $1 AND fk_owner = $2 AND name = $3
+        ORDER BY id
+        LIMIT 1
+        FOR UPDATE;",
+        parent_folder_id,
+        owner_id,
+        file_name
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(existing) = existing {
+        versions_model::save_version(&mut *conn, &existing).await?;
+        return sqlx::query_as!(
+            File,
+            "UPDATE files
+            SET file_type = $2, mime_type = $3, size = $4, last_modified = CURRENT_TIMESTAMP,
+            fk_blob = $5
+            WHERE id = $1
+            RETURNING id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+            fk_parent, fk_blob;",
+            existing.id,
+            file_type,
+            mime_type,
+            file_size,
+            hash
+        )
+        .fetch_one(&mut *conn)
+        .await;
+    }
+    sqlx::query_as!(
+        File,
+        "INSERT INTO files (name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob)
+        VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP, $5, $6, $7, $8)
+        RETURNING id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob;",
+        file_name,
+        file_type,
+        mime_type,
+        file_size,
+        false,
+        owner_id,
+        parent_folder_id,
+        hash
+    )
+    .fetch_one(&mut *conn)
+    .await
 }
 
 pub(super) fn validate_name(mut name: &str) -> bool {
     name = name.trim();
-    name.len() >= 1 && name.len() <= 255
-}
-
-fn build_file_path(file_id: i32) -> PathBuf {
-    let mut path = PathBuf::from(FILES_FOLDER);
-    path.push(file_id.to_string());
-    path
-}
-
-fn get_file_type(file_name: &str) -> Option<String> {
-    let path = Path::new(file_name);
-    let extension = path.extension()?;
-    let extension_str = extension.to_str()?.to_lowercase();
-    match extension_str.as_str() {
-        "txt" | "c" | "cpp" | "h" | "hpp" | "py" | "js" | "html" | "css" | "md" | "json"
-        | "xml" | "yaml" | "toml" | "rs" | "svelte" | "jsx" | "nim" | "go" | "java" | "php"
-        | "rb" | "lua" | "swift" | "kt" | "scala" | "pl" | "sh" | "bat" | "ps1" | "ts" | "asm"
-        | "config" | "yml" | "env" | "sql" | "gitignore" | "tsx" | "cs" => Some("Text".to_string()),
-        "png" | "jpg" | "jpeg" | "gif" | "bmp" | "svg" | "ico" | "webp" | "tiff" | "tif"
-        | "heif" | "heic" => Some("Image".to_string()),
-        _ => None,
-    }
+    !name.is_empty() && name.len() <= 255
+}
+
+/// Escapes the characters that have a special meaning in LIKE patterns.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
 }
@@ -1,9 +1,18 @@
 use super::{
+    blobs_model,
     files_model::{self, validate_name},
     folder::Folder,
+    reservations_model, Position, Reservation, Sort,
 };
-use crate::errors::{FileError, InternalError};
-use sqlx::PgPool;
+use crate::{
+    errors::{FileError, InternalError},
+    storage::StorageBackend,
+};
+use async_zip::{tokio::write::ZipFileWriter, Compression, ZipEntryBuilder};
+use sqlx::{PgConnection, PgPool};
+use std::collections::{HashMap, HashSet};
+use tokio::io::{self, AsyncWrite};
+use tokio_util::compat::FuturesAsyncWriteCompatExt;
 
 pub async fn init_root_folders(pg_pool: &PgPool, owner_id: i32) -> Result<(), InternalError> {
     for f in ["My Cloud", "Trash"] {
@@ -20,7 +29,12 @@ pub async fn new_folder(
     parent_folder_id: i32,
     owner_id: i32,
 ) -> Result<Folder, FileError> {
-    new_raw_folder(pg_pool, folder_name, Some(parent_folder_id), owner_id).await
+    // Shared folders are read-only, so the parent must be one of the user's folders
+    match find_folder(pg_pool, parent_folder_id, owner_id).await {
+        Ok(Some(_)) => new_raw_folder(pg_pool, folder_name, Some(parent_folder_id), owner_id).await,
+        Ok(None) => Err(FileError::NotFound),
+        Err(_) => Err(FileError::InternalError),
+    }
 }
 
 pub async fn get_root_folders(
@@ -38,22 +52,81 @@ pub async fn get_root_folders(
     .map_err(|_| InternalError("Failed to add folder to database".to_string()))
 }
 
-pub async fn get_folders(
+pub async fn get_personal_folder(pg_pool: &PgPool, user_id: i32) -> Result<Folder, InternalError> {
+    get_root_folder(pg_pool, user_id, "My Cloud").await
+}
+
+pub async fn get_trash_folder(pg_pool: &PgPool, user_id: i32) -> Result<Folder, InternalError> {
+    get_root_folder(pg_pool, user_id, "Trash").await
+}
+
+/// Returns the folder if it exists and belongs to the user.
+pub(super) async fn find_folder(
     pg_pool: &PgPool,
-    parent_folder_id: i32,
+    folder_id: i32,
     owner_id: i32,
-) -> Result<Vec<Folder>, InternalError> {
+) -> Result<Option<Folder>, sqlx::Error> {
     sqlx::query_as!(
         Folder,
         "SELECT *
         FROM folders
-        WHERE fk_owner = $1 AND fk_parent = $2;",
-        owner_id,
-        parent_folder_id
+        WHERE id = $1 AND fk_owner = $2;",
+        folder_id,
+        owner_id
     )
-    .fetch_all(pg_pool)
+    .fetch_optional(pg_pool)
+    .await
+}
+
+pub async fn get_readable_folder(
+    pg_pool: &PgPool,
+    folder_id: i32,
+    user_id: i32,
+) -> Result<Folder, InternalError> {
+    sqlx::query_as!(
+        Folder,
+        "SELECT *
+        FROM folders
+        WHERE id = $1 AND (fk_owner = $2 OR has_folder_read_access($1, $2));",
+        folder_id,
+        user_id
+    )
+    .fetch_one(pg_pool)
     .await
-    .map_err(|_| InternalError("Failed get the folders from the database".to_string()))
+    .map_err(|_| InternalError("Failed to get the folder".to_string()))
+}
+
+/// Returns the folders of the folder in the given order, starting after the position if there's
+/// one. All of them are returned when there's no limit.
+pub async fn get_folders(
+    pg_pool: &PgPool,
+    parent_folder_id: i32,
+    user_id: i32,
+    sort: Sort,
+    after: Option<&Position>,
+    limit: Option<i64>,
+) -> Result<Vec<Folder>, InternalError> {
+    let (condition, order) = sort.to_sql(true, 3);
+    // The folders can be read by their owner or by whoever the parent is shared with
+    let sql = format!(
+        "SELECT *
+        FROM folders
+        WHERE fk_parent = $2 AND (fk_owner = $1 OR has_folder_read_access($2, $1))
+        AND ($6 OR {condition})
+        ORDER BY {order}
+        LIMIT $7;"
+    );
+    sqlx::query_as::<_, Folder>(&sql)
+        .bind(user_id)
+        .bind(parent_folder_id)
+        .bind(after.map(|p| p.value.as_str()))
+        .bind(after.map(|p| p.name.as_str()))
+        .bind(after.map(|p| p.id))
+        .bind(after.is_none())
+        .bind(limit)
+        .fetch_all(pg_pool)
+        .await
+        .map_err(|_| InternalError("Failed get the folders from the database".to_string()))
 }
 
 pub async fn rename_folder(
@@ -79,6 +152,119 @@ pub async fn rename_folder(
     Ok(())
 }
 
+/// Stars or unstars the folder. Returns false if the user doesn't own such a folder.
+pub async fn set_folder_starred(
+    pg_pool: &PgPool,
+    folder_id: i32,
+    owner_id: i32,
+    starred: bool,
+) -> Result<bool, InternalError> {
+    // Root folders (My Cloud and Trash) can't be starred
+    sqlx::query!(
+        "UPDATE folders
+        SET starred = $3
+        WHERE id = $1 AND fk_owner = $2 AND fk_parent IS NOT null;",
+        folder_id,
+        owner_id,
+        starred
+    )
+    .execute(pg_pool)
+    .await
+    .map(|r| r.rows_affected() > 0)
+    .map_err(|_| InternalError("Failed to star the folder".to_string()))
+}
+
+/// Returns the starred folders of the user, wherever they are, except in the trash.
+pub async fn get_starred_folders(
+    pg_pool: &PgPool,
+    owner_id: i32,
+) -> Result<Vec<Folder>, InternalError> {
+    sqlx::query_as!(
+        Folder,
+        "SELECT *
+        FROM folders
+        WHERE fk_owner = $1 AND starred AND fk_parent IS NOT null
+        AND id NOT IN (SELECT folder_id FROM get_trash_tree($1))
+        ORDER BY name;",
+        owner_id
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the starred folders".to_string()))
+}
+
+/// Returns the path of each folder from its root folder, like "My Cloud/Photos/2023".
+pub async fn get_folder_paths(
+    pg_pool: &PgPool,
+    folder_ids: &[i32],
+) -> Result<HashMap<i32, String>, InternalError> {
+    let rows = sqlx::query!(
+        r#"SELECT id as "id!", (
+            SELECT string_agg(p.name, '/' ORDER BY p.depth DESC)
+            FROM get_folder_path(id) p
+        ) as "path!"
+        FROM unnest($1::integer[]) id;"#,
+        folder_ids
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the paths of the folders".to_string()))?;
+    Ok(rows.into_iter().map(|r| (r.id, r.path)).collect())
+}
+
+/// Returns the largest folders of the user with their size, which includes their subfolders.
+/// The root folders aren't part of them.
+pub async fn get_largest_folders(
+    pg_pool: &PgPool,
+    owner_id: i32,
+    limit: i64,
+) -> Result<Vec<(Folder, i64)>, InternalError> {
+    // Each folder is paired with itself and all the folders it's in,
+    // so that its files count for all of them at once
+    let rows = sqlx::query!(
+        r#"WITH RECURSIVE ancestors AS (
+            SELECT id as folder_id, id as ancestor_id, fk_parent
+            FROM folders
+            WHERE fk_owner = $1
+            UNION ALL
+            SELECT a.folder_id, f.id, f.fk_parent
+            FROM ancestors a
+            JOIN folders f ON f.id = a.fk_parent
+        ), sizes AS (
+            SELECT a.ancestor_id, SUM(fi.size)::bigint as size
+            FROM ancestors a
+            JOIN files fi ON fi.fk_parent = a.folder_id
+            GROUP BY a.ancestor_id
+        )
+        SELECT f.id, f.name, f.last_modified, f.starred, f.fk_owner, f.fk_parent,
+        s.size as "size!"
+        FROM sizes s
+        JOIN folders f ON f.id = s.ancestor_id
+        WHERE f.fk_parent IS NOT null
+        ORDER BY s.size DESC, f.id
+        LIMIT $2;"#,
+        owner_id,
+        limit
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the largest folders".to_string()))?;
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            let folder = Folder {
+                id: r.id,
+                name: r.name,
+                last_modified: r.last_modified,
+                starred: r.starred,
+                fk_owner: r.fk_owner,
+                fk_parent: r.fk_parent,
+            };
+            (folder, r.size)
+        })
+        .collect())
+}
+
 pub async fn folder_size(
     pg_pool: &PgPool,
     folder_id: i32,
@@ -98,7 +284,7 @@ async fn folder_size_no_filter(
 ) -> Result<i64, InternalError> {
     // Recursive query to get the storage of the folder and all its children
     let size = sqlx::query!(
-        "SELECT SUM(size) as size
+        "SELECT SUM(size)::bigint as size
         FROM files
         WHERE fk_parent IN (SELECT folder_id FROM get_folder_tree($1, $2));",
         folder_id,
@@ -119,10 +305,11 @@ async fn folder_size_filter(
 ) -> Result<i64, InternalError> {
     // Recursive query to get the storage of the folder and all its children
     let size = sqlx::query!(
-        "SELECT SUM(size) as size
+        "SELECT SUM(size)::bigint as size
         FROM files
         WHERE fk_parent IN (SELECT folder_id FROM get_folder_tree($1, $2))
-        AND file_type = $3;",
+        AND (file_type = $3 OR mime_type = $3
+            OR (right($3, 2) = '/*' AND starts_with(mime_type, left($3, -1))));",
         folder_id,
         owner_id,
         filter
@@ -134,6 +321,57 @@ async fn folder_size_filter(
     Ok(size.unwrap_or(0))
 }
 
+/// Writes a ZIP archive of the folder and all its content to the writer, one file at a time.
+pub async fn write_folder_archive<W: AsyncWrite + Unpin>(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    folder_id: i32,
+    owner_id: i32,
+    writer: W,
+) -> Result<(), InternalError> {
+    let folders = get_tree_folders(pg_pool, folder_id, owner_id).await?;
+    let files = files_model::get_tree_files(pg_pool, folder_id, owner_id).await?;
+    // Items with the same name in a folder get a number added, entries must be unique
+    let mut used_paths = HashSet::new();
+    let folder_paths = build_folder_paths(&folders, folder_id, &mut used_paths);
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    // Add the folders first, so that empty folders are preserved too
+    for folder in &folders {
+        // The tree may have changed between the two queries, what moved out of it is skipped
+        let Some(path) = folder_paths.get(&folder.id) else {
+            continue;
+        };
+        let entry = ZipEntryBuilder::new(format!("{}/", path).into(), Compression::Stored);
+        zip.write_entry_whole(entry, &[])
+            .await
+            .map_err(|_| InternalError("Failed to write the archive".to_string()))?;
+    }
+    for file in &files {
+        let Some(parent_path) = folder_paths.get(&file.fk_parent) else {
+            continue;
+        };
+        let path = unique_archive_path(&mut used_paths, parent_path, &file.name, true);
+        let mut content = files_model::read_file_content(storage, file, None).await?;
+        let mut entry_writer = zip
+            .write_entry_stream(ZipEntryBuilder::new(path.into(), Compression::Deflate))
+            .await
+            .map_err(|_| InternalError("Failed to write the archive".to_string()))?
+            .compat_write();
+        io::copy(&mut content, &mut entry_writer)
+            .await
+            .map_err(|_| InternalError(format!("Failed to archive file '{}'", file.id)))?;
+        entry_writer
+            .into_inner()
+            .close()
+            .await
+            .map_err(|_| InternalError("Failed to write the archive".to_string()))?;
+    }
+    zip.close()
+        .await
+        .map_err(|_| InternalError("Failed to write the archive".to_string()))?;
+    Ok(())
+}
+
 pub async fn move_folder(
     pg_pool: &PgPool,
     folder_id: i32,
@@ -155,58 +393,279 @@ pub async fn move_folder(
     Ok(())
 }
 
+/// Copies the folder and everything in it into the destination folder,
+/// or next to the original if none is given. Nothing is copied if any part of it fails.
+pub async fn duplicate_folder(
+    pg_pool: &PgPool,
+    folder_id: i32,
+    owner_id: i32,
+    to_folder_id: Option<i32>,
+    reservation: &Reservation,
+) -> Result<Folder, InternalError> {
+    let err = || InternalError(format!("Failed to duplicate folder {}", folder_id));
+    let mut tx = pg_pool.begin().await.map_err(|_| err())?;
+    let folder = copy_folder_tree(&mut tx, folder_id, owner_id, to_folder_id)
+        .await
+        .map_err(|_| err())?;
+    reservations_model::use_reservation(&mut tx, reservation)
+        .await
+        .map_err(|_| err())?;
+    tx.commit().await.map_err(|_| err())?;
+    Ok(folder)
+}
+
 pub async fn delete_folder(
     pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
     folder_id: i32,
     owner_id: i32,
     preserve_parent: bool,
 ) -> Result<(), InternalError> {
-    // Delete the files from the database
-    let files_ids = sqlx::query!(
-        "DELETE FROM files
-        WHERE fk_parent IN (SELECT folder_id FROM get_folder_tree($1, $2))
-        RETURNING id;",
+    let err = || InternalError(format!("Failed to delete folder {}", folder_id));
+    let mut tx = pg_pool.begin().await.map_err(|_| err())?;
+    // Delete the files and their previous versions from the database
+    let blobs: Vec<String> = sqlx::query!(
+        r#"WITH deleted AS (
+            DELETE FROM files
+            WHERE fk_parent IN (SELECT folder_id FROM get_folder_tree($1, $2))
+            RETURNING id, fk_blob
+        ), deleted_versions AS (
+            DELETE FROM file_versions
+            WHERE fk_file IN (SELECT id FROM deleted)
+            RETURNING fk_blob
+        )
+        SELECT fk_blob as "fk_blob!" FROM deleted
+        UNION ALL
+        SELECT fk_blob FROM deleted_versions;"#,
         folder_id,
         owner_id
     )
-    .fetch_all(pg_pool)
+    .fetch_all(&mut *tx)
     .await
-    .map_err(|_| InternalError(format!("Failed to delete files in folder {}", folder_id)))?;
-    // Delete the files from the storage
-    for file_id in files_ids {
-        files_model::delete_file_content(file_id.id).await?;
-    }
-    // Delete the folders from the database
-    // TODO: Check that the folder is not the root folder
+    .map_err(|_| err())?
+    .into_iter()
+    .map(|r| r.fk_blob)
+    .collect();
+    blobs_model::release_blobs(&mut tx, &blobs)
+        .await
+        .map_err(|_| err())?;
+    // Delete the folders from the database, root folders are only emptied
     let preserved_folder_id = if preserve_parent { folder_id } else { -1 };
     sqlx::query!(
         "DELETE FROM folders
-        WHERE id IN (SELECT folder_id FROM get_folder_tree($1, $2)) AND id <> $3;",
+        WHERE id IN (SELECT folder_id FROM get_folder_tree($1, $2)) AND id <> $3
+        AND fk_parent IS NOT null;",
         folder_id,
         owner_id,
         preserved_folder_id
     )
-    .fetch_all(pg_pool)
+    .execute(&mut *tx)
     .await
-    .map_err(|_| InternalError(format!("Failed to delete folders in folder {}", folder_id)))?;
-    Ok(())
+    .map_err(|_| err())?;
+    tx.commit().await.map_err(|_| err())?;
+    // Delete the content that isn't used by other files anymore
+    blobs_model::purge_blobs(pg_pool, storage, &blobs).await
 }
 
 pub(super) async fn delete_user_folders(
-    pg_pool: &PgPool,
+    conn: &mut PgConnection,
     owner_id: i32,
-) -> Result<(), InternalError> {
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         "DELETE FROM folders
         WHERE fk_owner = $1;",
         owner_id
     )
-    .execute(pg_pool)
-    .await
-    .map_err(|_| InternalError("Failed to delete user folders".to_string()))?;
+    .execute(conn)
+    .await?;
     Ok(())
 }
 
+async fn copy_folder_tree(
+    conn: &mut PgConnection,
+    folder_id: i32,
+    owner_id: i32,
+    to_folder_id: Option<i32>,
+) -> Result<Folder, sqlx::Error> {
+    let folders = sqlx::query_as!(
+        Folder,
+        "SELECT *
+        FROM folders
+        WHERE id IN (SELECT folder_id FROM get_folder_tree($1, $2));",
+        folder_id,
+        owner_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    // Root folders (My Cloud and Trash) can't be copied
+    let parent_id = folders
+        .iter()
+        .find(|f| f.id == folder_id)
+        .and_then(|f| f.fk_parent)
+        .ok_or(sqlx::Error::RowNotFound)?;
+    // The copy can't go inside the folder being copied
+    let to_folder_id = to_folder_id.unwrap_or(parent_id);
+    if folders.iter().any(|f| f.id == to_folder_id) {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    sqlx::query!(
+        "SELECT id
+        FROM folders
+        WHERE id = $1 AND fk_owner = $2;",
+        to_folder_id,
+        owner_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    // Copy the folders from the top, so that the parent of each copy exists before it
+    let mut children: HashMap<i32, Vec<&Folder>> = HashMap::new();
+    for f in folders.iter().filter(|f| f.id != folder_id) {
+        children.entry(f.fk_parent.unwrap()).or_default().push(f);
+    }
+    let mut copies: HashMap<i32, i32> = HashMap::new();
+    let mut queue = vec![(folder_id, to_folder_id)];
+    let mut root_copy = None;
+    while let Some((id, new_parent_id)) = queue.pop() {
+        let copy = sqlx::query_as!(
+            Folder,
+            "INSERT INTO folders (name, last_modified, starred, fk_owner, fk_parent)
+            SELECT name, CURRENT_TIMESTAMP, starred, fk_owner, $2
+            FROM folders
+            WHERE id = $1
+            RETURNING *;",
+            id,
+            new_parent_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        copies.insert(id, copy.id);
+        for child in children.get(&id).into_iter().flatten() {
+            queue.push((child.id, copy.id));
+        }
+        if id == folder_id {
+            root_copy = Some(copy);
+        }
+    }
+    // The copies of the files share the content of the original ones
+    let (old_ids, new_ids): (Vec<i32>, Vec<i32>) = copies.into_iter().unzip();
+    sqlx::query!(
+        "INSERT INTO files (name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob)
+        SELECT f.name, f.file_type, f.mime_type, f.size, CURRENT_TIMESTAMP, f.starred, f.fk_owner,
+        c.new_id, f.fk_blob
+        FROM files f
+        JOIN unnest($1::integer[], $2::integer[]) c(old_id, new_id) ON f.fk_parent = c.old_id;",
+        &old_ids,
+        &new_ids
+    )
+    .execute(&mut *conn)
+    .await?;
+    blobs_model::retain_folder_blobs(&mut *conn, &new_ids).await?;
+    root_copy.ok_or(sqlx::Error::RowNotFound)
+}
+
+async fn get_tree_folders(
+    pg_pool: &PgPool,
+    folder_id: i32,
+    owner_id: i32,
+) -> Result<Vec<Folder>, InternalError> {
+    sqlx::query_as!(
+        Folder,
+        "SELECT *
+        FROM folders
+        WHERE id IN (SELECT folder_id FROM get_folder_tree($1, $2));",
+        folder_id,
+        owner_id
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError(format!("Failed to get the folders in folder {}", folder_id)))
+}
+
+/// Maps each folder of the tree to its path, starting from the root folder's name, and records
+/// the paths as used. Folders that aren't connected to the root are left out.
+fn build_folder_paths(
+    folders: &[Folder],
+    root_id: i32,
+    used_paths: &mut HashSet<String>,
+) -> HashMap<i32, String> {
+    let by_id: HashMap<i32, &Folder> = folders.iter().map(|f| (f.id, f)).collect();
+    let mut paths = HashMap::new();
+    for folder in folders {
+        // Walk up until a folder with a known path (or the root) is found
+        let mut missing = Vec::new();
+        let mut current = folder;
+        let path = loop {
+            if let Some(p) = paths.get(&current.id) {
+                break Some(String::clone(p));
+            }
+            if current.id == root_id {
+                let path = sanitize_archive_name(&current.name);
+                used_paths.insert(path.clone());
+                paths.insert(current.id, path.clone());
+                break Some(path);
+            }
+            missing.push(current);
+            match current.fk_parent.and_then(|id| by_id.get(&id)) {
+                Some(parent) => current = parent,
+                None => break None,
+            }
+        };
+        let Some(mut path) = path else {
+            continue;
+        };
+        for f in missing.into_iter().rev() {
+            path = unique_archive_path(used_paths, &path, &f.name, false);
+            paths.insert(f.id, path.clone());
+        }
+    }
+    paths
+}
+
+/// Returns the path of the item in the parent folder, with a number added to its name if the
+/// path is already used, and records it as used.
+fn unique_archive_path(
+    used_paths: &mut HashSet<String>,
+    parent_path: &str,
+    name: &str,
+    is_file: bool,
+) -> String {
+    let name = sanitize_archive_name(name);
+    // The number goes before the extension of files
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if is_file && !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name.as_str(), String::new()),
+    };
+    let mut path = format!("{}/{}", parent_path, name);
+    let mut number = 1;
+    while !used_paths.insert(path.clone()) {
+        path = format!("{}/{} ({}){}", parent_path, stem, number, extension);
+        number += 1;
+    }
+    path
+}
+
+fn sanitize_archive_name(name: &str) -> String {
+    name.replace(['/', '\\'], "_")
+}
+
+async fn get_root_folder(
+    pg_pool: &PgPool,
+    user_id: i32,
+    folder_name: &str,
+) -> Result<Folder, InternalError> {
+    sqlx::query_as!(
+        Folder,
+        "SELECT * FROM folders
+        WHERE fk_owner = $1 AND fk_parent IS null AND name = $2;",
+        user_id,
+        folder_name
+    )
+    .fetch_one(pg_pool)
+    .await
+    .map_err(|_| InternalError(format!("Failed to get the '{}' folder", folder_name)))
+}
+
 async fn new_raw_folder(
     pg_pool: &PgPool,
     folder_name: &str,
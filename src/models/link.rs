@@ -0,0 +1,57 @@
+use sqlx::types::chrono::NaiveDateTime;
+
+#[derive(sqlx::FromRow)]
+pub struct Link {
+    pub(super) id: i32,
+    pub(super) token: String,
+    pub(super) fk_file: Option<i32>,
+    pub(super) fk_folder: Option<i32>,
+    pub(super) fk_owner: i32,
+    pub(super) item_name: String,
+    pub(super) password: Option<String>,
+    pub(super) expires_at: Option<NaiveDateTime>,
+    pub(super) max_downloads: Option<i32>,
+    pub(super) downloads: i32,
+}
+
+impl Link {
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn get_token(&self) -> &String {
+        &self.token
+    }
+
+    pub fn get_fk_file(&self) -> &Option<i32> {
+        &self.fk_file
+    }
+
+    pub fn get_fk_folder(&self) -> &Option<i32> {
+        &self.fk_folder
+    }
+
+    pub fn get_fk_owner(&self) -> i32 {
+        self.fk_owner
+    }
+
+    pub fn get_item_name(&self) -> &String {
+        &self.item_name
+    }
+
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    pub fn get_expires_at(&self) -> &Option<NaiveDateTime> {
+        &self.expires_at
+    }
+
+    pub fn get_max_downloads(&self) -> &Option<i32> {
+        &self.max_downloads
+    }
+
+    pub fn get_downloads(&self) -> i32 {
+        self.downloads
+    }
+}
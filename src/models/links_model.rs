@@ -0,0 +1,205 @@
+use super::link::Link;
+use crate::errors::{InternalError, LinkError};
+use rand_chacha::ChaCha8Rng;
+use rand_core::RngCore;
+use sqlx::PgPool;
+
+pub async fn new_file_link(
+    pg_pool: &PgPool,
+    token: &str,
+    file_id: i32,
+    owner_id: i32,
+    password: Option<&str>,
+    expires_at: Option<i64>,
+    max_downloads: Option<i32>,
+) -> Result<Link, LinkError> {
+    let password = hash_password(password).await?;
+    let link_id = sqlx::query!(
+        "INSERT INTO links (token, fk_file, fk_owner, password, expires_at, max_downloads,
+        downloads, created_at)
+        SELECT $1, id, fk_owner, $4, to_timestamp($5::bigint)::timestamp, $6, 0, CURRENT_TIMESTAMP
+        FROM files
+        WHERE id = $2 AND fk_owner = $3
+        RETURNING id;",
+        token,
+        file_id,
+        owner_id,
+        password,
+        expires_at,
+        max_downloads
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .map_err(|_| LinkError::InternalError)?
+    .ok_or(LinkError::NotFound)?
+    .id;
+    get_link_by_id(pg_pool, link_id).await
+}
+
+pub async fn new_folder_link(
+    pg_pool: &PgPool,
+    token: &str,
+    folder_id: i32,
+    owner_id: i32,
+    password: Option<&str>,
+    expires_at: Option<i64>,
+    max_downloads: Option<i32>,
+) -> Result<Link, LinkError> {
+    let password = hash_password(password).await?;
+    // Root folders (My Cloud and Trash) can't be shared
+    let link_id = sqlx::query!(
+        "INSERT INTO links (token, fk_folder, fk_owner, password, expires_at, max_downloads,
+        downloads, created_at)
+        SELECT $1, id, fk_owner, $4, to_timestamp($5::bigint)::timestamp, $6, 0, CURRENT_TIMESTAMP
+        FROM folders
+        WHERE id = $2 AND fk_owner = $3 AND fk_parent IS NOT null
+        RETURNING id;",
+        token,
+        folder_id,
+        owner_id,
+        password,
+        expires_at,
+        max_downloads
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .map_err(|_| LinkError::InternalError)?
+    .ok_or(LinkError::NotFound)?
+    .id;
+    get_link_by_id(pg_pool, link_id).await
+}
+
+/// Returns the links of the user that can still be used.
+pub async fn get_links(pg_pool: &PgPool, owner_id: i32) -> Result<Vec<Link>, InternalError> {
+    sqlx::query_as!(
+        Link,
+        r#"SELECT l.id, l.token, l.fk_file, l.fk_folder, l.fk_owner,
+        COALESCE(fi.name, fo.name) as "item_name!", l.password, l.expires_at, l.max_downloads,
+        l.downloads
+        FROM links l
+        LEFT JOIN files fi ON fi.id = l.fk_file
+        LEFT JOIN folders fo ON fo.id = l.fk_folder
+        WHERE l.fk_owner = $1
+        AND (l.expires_at IS null OR l.expires_at > CURRENT_TIMESTAMP)
+        AND (l.max_downloads IS null OR l.downloads < l.max_downloads)
+        ORDER BY l.id;"#,
+        owner_id
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the links".to_string()))
+}
+
+pub async fn delete_link(
+    pg_pool: &PgPool,
+    link_id: i32,
+    owner_id: i32,
+) -> Result<(), InternalError> {
+    sqlx::query!(
+        "DELETE FROM links
+        WHERE id = $1 AND fk_owner = $2;",
+        link_id,
+        owner_id
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to delete the link".to_string()))?;
+    Ok(())
+}
+
+/// Returns the link with the token if it can still be used, checking its password.
+pub async fn get_link(
+    pg_pool: &PgPool,
+    token: &str,
+    password: Option<&str>,
+) -> Result<Link, LinkError> {
+    // Links to items in the trash stop working until they are restored
+    let link = sqlx::query_as!(
+        Link,
+        r#"SELECT l.id, l.token, l.fk_file, l.fk_folder, l.fk_owner,
+        COALESCE(fi.name, fo.name) as "item_name!", l.password, l.expires_at, l.max_downloads,
+        l.downloads
+        FROM links l
+        LEFT JOIN files fi ON fi.id = l.fk_file
+        LEFT JOIN folders fo ON fo.id = l.fk_folder
+        WHERE l.token = $1
+        AND (l.expires_at IS null OR l.expires_at > CURRENT_TIMESTAMP)
+        AND (l.max_downloads IS null OR l.downloads < l.max_downloads)
+        AND NOT is_in_trash(COALESCE(fi.fk_parent, fo.id));"#,
+        token
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .map_err(|_| LinkError::InternalError)?
+    .ok_or(LinkError::NotFound)?;
+    if let Some(hash) = &link.password {
+        let password = password.ok_or(LinkError::WrongPassword)?;
+        let (password, hash) = (password.to_string(), hash.clone());
+        // Hashing takes a while on purpose, so it's kept off the async threads
+        let is_pwd_correct = tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash))
+            .await
+            .map_err(|_| LinkError::InternalError)?
+            .map_err(|_| LinkError::InternalError)?;
+        if !is_pwd_correct {
+            return Err(LinkError::WrongPassword);
+        }
+    }
+    Ok(link)
+}
+
+/// Same as `get_link`, but also counts a download of the link.
+pub async fn use_link(
+    pg_pool: &PgPool,
+    token: &str,
+    password: Option<&str>,
+) -> Result<Link, LinkError> {
+    let link = get_link(pg_pool, token, password).await?;
+    // The limit is checked again, in case the last download was used in the meantime
+    sqlx::query!(
+        "UPDATE links
+        SET downloads = downloads + 1
+        WHERE id = $1 AND (max_downloads IS null OR downloads < max_downloads)
+        RETURNING id;",
+        link.id
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .map_err(|_| LinkError::InternalError)?
+    .ok_or(LinkError::NotFound)?;
+    Ok(link)
+}
+
+async fn get_link_by_id(pg_pool: &PgPool, link_id: i32) -> Result<Link, LinkError> {
+    sqlx::query_as!(
+        Link,
+        r#"SELECT l.id, l.token, l.fk_file, l.fk_folder, l.fk_owner,
+        COALESCE(fi.name, fo.name) as "item_name!", l.password, l.expires_at, l.max_downloads,
+        l.downloads
+        FROM links l
+        LEFT JOIN files fi ON fi.id = l.fk_file
+        LEFT JOIN folders fo ON fo.id = l.fk_folder
+        WHERE l.id = $1;"#,
+        link_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .map_err(|_| LinkError::InternalError)
+}
+
+/// Returns a new random token for a link.
+pub fn new_token(rng: &mut ChaCha8Rng) -> String {
+    let mut bytes = [0u8; 16];
+    rng.fill_bytes(&mut bytes);
+    format!("{:032x}", u128::from_le_bytes(bytes))
+}
+
+async fn hash_password(password: Option<&str>) -> Result<Option<String>, LinkError> {
+    let Some(password) = password.map(str::to_string) else {
+        return Ok(None);
+    };
+    tokio::task::spawn_blocking(move || bcrypt::hash(password, bcrypt::DEFAULT_COST))
+        .await
+        .map_err(|_| LinkError::InternalError)?
+        .map(Some)
+        .map_err(|_| LinkError::InternalError)
+}
@@ -0,0 +1,94 @@
+use super::{file::File, folder::Folder};
+use sqlx::types::chrono::NaiveDateTime;
+
+/// What the content of a folder is sorted by.
+/// Folders have no size or type, so they are sorted by name instead.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Size,
+    LastModified,
+    Type,
+}
+
+#[derive(Clone, Copy)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+/// The sort values of the last item of a page, the next page starts right after it.
+pub struct Position {
+    pub value: String,
+    pub name: String,
+    pub id: i32,
+}
+
+impl Sort {
+    pub fn file_position(&self, file: &File) -> Position {
+        let value = match self.key {
+            SortKey::Name => file.name.clone(),
+            SortKey::Size => file.size.to_string(),
+            SortKey::LastModified => file.last_modified.to_string(),
+            SortKey::Type => file.file_type.clone().unwrap_or_default(),
+        };
+        Position {
+            value,
+            name: file.name.clone(),
+            id: file.id,
+        }
+    }
+
+    pub fn folder_position(&self, folder: &Folder) -> Position {
+        let value = match self.key {
+            SortKey::LastModified => folder.last_modified.to_string(),
+            _ => folder.name.clone(),
+        };
+        Position {
+            value,
+            name: folder.name.clone(),
+            id: folder.id,
+        }
+    }
+
+    /// Returns whether the value of the position can be compared with the sort column,
+    /// since positions come back from the clients.
+    pub fn is_valid_position(&self, position: &Position, is_folder: bool) -> bool {
+        match (self.key, is_folder) {
+            (SortKey::LastModified, _) => {
+                NaiveDateTime::parse_from_str(&position.value, "%Y-%m-%d %H:%M:%S%.f").is_ok()
+            }
+            (SortKey::Size, false) => position.value.parse::<i64>().is_ok(),
+            _ => true,
+        }
+    }
+
+    /// Returns the condition on the rows after the position, whose values are bound to
+    /// the parameters starting at `first_param`, and the ORDER BY clause.
+    /// Only constant SQL comes out of here, so it can be put in the queries as is.
+    pub(super) fn to_sql(self, is_folder: bool, first_param: usize) -> (String, String) {
+        let key = match (self.key, is_folder) {
+            (SortKey::LastModified, _) => Some(("last_modified", "timestamp")),
+            (_, true) | (SortKey::Name, _) => None,
+            (SortKey::Size, _) => Some(("size", "bigint")),
+            (SortKey::Type, _) => Some(("COALESCE(file_type, '')", "text")),
+        };
+        let (op, dir) = if self.descending {
+            ("<", "DESC")
+        } else {
+            (">", "ASC")
+        };
+        let (value, name, id) = (first_param, first_param + 1, first_param + 2);
+        // Ties are broken by name, and then by id so that the order is total
+        match key {
+            Some((column, sql_type)) => (
+                format!("({column}, name, id) {op} (${value}::text::{sql_type}, ${name}, ${id})"),
+                format!("{column} {dir}, name {dir}, id {dir}"),
+            ),
+            None => (
+                format!("(name, id) {op} (${name}, ${id})"),
+                format!("name {dir}, id {dir}"),
+            ),
+        }
+    }
+}
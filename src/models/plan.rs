@@ -0,0 +1,26 @@
+/// A storage plan that users can be on, with the number of users on it.
+#[derive(sqlx::FromRow)]
+pub struct Plan {
+    pub(super) id: i32,
+    pub(super) name: String,
+    pub(super) max_storage_mb: i64,
+    pub(super) users: i64,
+}
+
+impl Plan {
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_max_storage_mb(&self) -> i64 {
+        self.max_storage_mb
+    }
+
+    pub fn get_users(&self) -> i64 {
+        self.users
+    }
+}
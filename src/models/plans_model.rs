@@ -0,0 +1,62 @@
+use super::Plan;
+use crate::errors::InternalError;
+use sqlx::PgPool;
+
+/// Creates the plan, or changes its quota if it already exists.
+pub async fn set_plan(
+    pg_pool: &PgPool,
+    name: &str,
+    max_storage_mb: i64,
+) -> Result<(), InternalError> {
+    sqlx::query!(
+        "INSERT INTO plans (name, max_storage_mb)
+        VALUES ($1, $2)
+        ON CONFLICT (name) DO UPDATE SET max_storage_mb = EXCLUDED.max_storage_mb;",
+        name,
+        max_storage_mb
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError("Error while saving the plan".to_string()))?;
+    Ok(())
+}
+
+pub async fn get_plans(pg_pool: &PgPool) -> Result<Vec<Plan>, InternalError> {
+    sqlx::query_as!(
+        Plan,
+        r#"SELECT p.id, p.name, p.max_storage_mb,
+        (SELECT COUNT(*) FROM users WHERE fk_plan = p.id) as "users!"
+        FROM plans p
+        ORDER BY p.name;"#
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Error while fetching plans".to_string()))
+}
+
+pub async fn get_plan_by_name(pg_pool: &PgPool, name: &str) -> Result<Option<Plan>, InternalError> {
+    sqlx::query_as!(
+        Plan,
+        r#"SELECT p.id, p.name, p.max_storage_mb,
+        (SELECT COUNT(*) FROM users WHERE fk_plan = p.id) as "users!"
+        FROM plans p
+        WHERE p.name = $1;"#,
+        name
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .map_err(|_| InternalError("Error while fetching plan".to_string()))
+}
+
+/// Deletes the plan, its users go back to the default quota.
+pub async fn delete_plan(pg_pool: &PgPool, plan_id: i32) -> Result<(), InternalError> {
+    sqlx::query!(
+        "DELETE FROM plans
+        WHERE id = $1;",
+        plan_id
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError("Error while deleting plan".to_string()))?;
+    Ok(())
+}
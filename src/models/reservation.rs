@@ -0,0 +1,6 @@
+/// Storage set aside for a user while files are being added, so that concurrent
+/// uploads can't go over the quota together.
+#[derive(sqlx::FromRow)]
+pub struct Reservation {
+    pub(super) id: i32,
+}
@@ -0,0 +1,286 @@
+use super::{blobs_model, Reservation};
+use crate::{
+    errors::{InternalError, QuotaError},
+    MAX_STORAGE_MB, PHYSICAL_QUOTA,
+};
+use sqlx::{PgConnection, PgPool};
+
+/// How long a reservation is kept if it's neither used nor released.
+const RESERVATION_TTL_SECS: i64 = 60 * 60;
+
+/// Sets the bytes aside for the user, if they fit in the quota with the files of the user
+/// and the other reservations. The reservation must then be used or released.
+pub async fn reserve_storage(
+    pg_pool: &PgPool,
+    user_id: i32,
+    bytes: i64,
+) -> Result<Reservation, QuotaError> {
+    reserve_within(pg_pool, user_id, bytes, *MAX_STORAGE_MB, *PHYSICAL_QUOTA).await
+}
+
+/// Does the reservation with the settings given instead of the ones of the environment.
+async fn reserve_within(
+    pg_pool: &PgPool,
+    user_id: i32,
+    bytes: i64,
+    default_max_storage_mb: i64,
+    physical_quota: bool,
+) -> Result<Reservation, QuotaError> {
+    let mut tx = pg_pool
+        .begin()
+        .await
+        .map_err(|_| QuotaError::InternalError)?;
+    // Adding or removing files locks the user too, so while it's locked the usage can't change
+    // and no other reservation can be made
+    let user = sqlx::query!(
+        r#"SELECT u.used_bytes, COALESCE(u.max_storage_mb, p.max_storage_mb, $2) as "max_storage_mb!"
+        FROM users u
+        LEFT JOIN plans p ON p.id = u.fk_plan
+        WHERE u.id = $1
+        FOR UPDATE OF u;"#,
+        user_id,
+        default_max_storage_mb
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| QuotaError::InternalError)?;
+    let used_bytes = if physical_quota {
+        blobs_model::get_physical_usage(&mut tx, user_id)
+            .await
+            .map_err(|_| QuotaError::InternalError)?
+    } else {
+        user.used_bytes
+    };
+    let reserved_bytes = sqlx::query!(
+        r#"SELECT COALESCE(SUM(bytes), 0)::bigint as "bytes!"
+        FROM storage_reservations
+        WHERE fk_user = $1 AND expires_at > CURRENT_TIMESTAMP;"#,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| QuotaError::InternalError)?
+    .bytes;
+    let space_left = user.max_storage_mb * 1_000_000 - used_bytes - reserved_bytes;
+    if bytes > space_left {
+        return Err(QuotaError::NotEnoughSpace(space_left));
+    }
+    let reservation = sqlx::query_as!(
+        Reservation,
+        "INSERT INTO storage_reservations (bytes, expires_at, fk_user)
+        VALUES ($1, CURRENT_TIMESTAMP + make_interval(secs => $2), $3)
+        RETURNING id;",
+        bytes,
+        RESERVATION_TTL_SECS as f64,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| QuotaError::InternalError)?;
+    tx.commit().await.map_err(|_| QuotaError::InternalError)?;
+    Ok(reservation)
+}
+
+/// Deletes the reservation in the transaction that adds the files, so that the bytes
+/// go from reserved to used at once.
+pub(super) async fn use_reservation(
+    conn: &mut PgConnection,
+    reservation: &Reservation,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM storage_reservations
+        WHERE id = $1;",
+        reservation.id
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Gives the reserved bytes back, when the files couldn't be added.
+pub async fn release_storage(
+    pg_pool: &PgPool,
+    reservation: Reservation,
+) -> Result<(), InternalError> {
+    sqlx::query!(
+        "DELETE FROM storage_reservations
+        WHERE id = $1;",
+        reservation.id
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to release the storage".to_string()))?;
+    Ok(())
+}
+
+/// Deletes the reservations that were never used nor released.
+pub async fn purge_expired(pg_pool: &PgPool) -> Result<u64, InternalError> {
+    sqlx::query!(
+        "DELETE FROM storage_reservations
+        WHERE expires_at <= CURRENT_TIMESTAMP;"
+    )
+    .execute(pg_pool)
+    .await
+    .map(|r| r.rows_affected())
+    .map_err(|_| InternalError("Failed to purge the storage reservations".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{files_model, upload_session::UploadSession, upload_sessions_model, TempFile},
+        storage::MemoryStorage,
+    };
+    use rand_core::{OsRng, RngCore};
+    use sqlx::Executor;
+    use std::sync::Arc;
+
+    const QUOTA_MB: i64 = 1;
+    const FILE_SIZE: usize = 400_000;
+    const UPLOADS: usize = 8;
+
+    /// Creates the tables, and a user whose quota fits only some of the uploads.
+    /// Returns the id of the user and of their folder.
+    async fn setup(pg_pool: &PgPool) -> (i32, i32) {
+        pg_pool
+            .execute(
+                [
+                    include_str!("../../schema/extensions.sql"),
+                    include_str!("../../schema/plans.sql"),
+                    include_str!("../../schema/users.sql"),
+                    include_str!("../../schema/folders.sql"),
+                    include_str!("../../schema/blobs.sql"),
+                    include_str!("../../schema/blob_reservations.sql"),
+                    include_str!("../../schema/thumbnails.sql"),
+                    include_str!("../../schema/files.sql"),
+                    include_str!("../../schema/file_versions.sql"),
+                    include_str!("../../schema/upload_sessions.sql"),
+                    include_str!("../../schema/storage_reservations.sql"),
+                    include_str!("../../schema/used_bytes.sql"),
+                ]
+                .concat()
+                .as_str(),
+            )
+            .await
+            .unwrap();
+        let user_id = sqlx::query_scalar(
+            "INSERT INTO users (username, email, password, max_storage_mb)
+            VALUES ('user', 'user@example.com', '', $1)
+            RETURNING id;",
+        )
+        .bind(QUOTA_MB)
+        .fetch_one(pg_pool)
+        .await
+        .unwrap();
+        let folder_id = sqlx::query_scalar(
+            "INSERT INTO folders (name, last_modified, starred, fk_owner)
+            VALUES ('My Cloud', CURRENT_TIMESTAMP, false, $1)
+            RETURNING id;",
+        )
+        .bind(user_id)
+        .fetch_one(pg_pool)
+        .await
+        .unwrap();
+        (user_id, folder_id)
+    }
+
+    /// Uploads a file with its own content the way the routes do, either at once or by
+    /// finishing an upload session.
+    async fn upload(
+        pg_pool: &PgPool,
+        storage: &MemoryStorage,
+        user_id: i32,
+        folder_id: i32,
+        n: usize,
+    ) -> Result<(), QuotaError> {
+        // Kept out of the folder of the files
+        let mut content = TempFile::create_in(&std::env::temp_dir(), OsRng.next_u64())
+            .await
+            .unwrap();
+        let mut data = vec![0; FILE_SIZE];
+        OsRng.fill_bytes(&mut data);
+        content.write_chunk(&data).await.unwrap();
+        let reservation = match reserve_within(pg_pool, user_id, content.get_size(), 0, false).await
+        {
+            Ok(reservation) => reservation,
+            Err(e) => {
+                content.discard().await;
+                return Err(e);
+            }
+        };
+        let name = format!("file{}", n);
+        let res = if n.is_multiple_of(2) {
+            files_model::new_file(
+                pg_pool,
+                storage,
+                &name,
+                content,
+                folder_id,
+                user_id,
+                &reservation,
+            )
+            .await
+        } else {
+            let session: UploadSession = sqlx::query_as(
+                "INSERT INTO upload_sessions (id, name, size, created_at, fk_owner, fk_parent)
+                VALUES ($1, $2, $3, CURRENT_TIMESTAMP, $4, $5)
+                RETURNING id, name, size, fk_owner, fk_parent;",
+            )
+            .bind(format!("{:016x}", OsRng.next_u64()))
+            .bind(&name)
+            .bind(content.get_size())
+            .bind(user_id)
+            .bind(folder_id)
+            .fetch_one(pg_pool)
+            .await
+            .unwrap();
+            upload_sessions_model::finish_session(pg_pool, storage, &session, content, &reservation)
+                .await
+        };
+        if res.is_err() {
+            let _ = release_storage(pg_pool, reservation).await;
+            panic!("the file wasn't added");
+        }
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn concurrent_uploads(pg_pool: PgPool) {
+        let (user_id, folder_id) = setup(&pg_pool).await;
+        let storage = Arc::new(MemoryStorage::default());
+        let tasks: Vec<_> = (0..UPLOADS)
+            .map(|n| {
+                let pg_pool = pg_pool.clone();
+                let storage = storage.clone();
+                tokio::spawn(async move { upload(&pg_pool, &storage, user_id, folder_id, n).await })
+            })
+            .collect();
+        let mut uploaded = 0;
+        for task in tasks {
+            match task.await.unwrap() {
+                Ok(()) => uploaded += 1,
+                Err(QuotaError::NotEnoughSpace(_)) => {}
+                Err(QuotaError::InternalError) => panic!("the reservation failed"),
+            }
+        }
+        let fitting = (QUOTA_MB * 1_000_000) as usize / FILE_SIZE;
+        assert_eq!(uploaded, fitting);
+        let (files, used_bytes, reserved): (i64, i64, i64) = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM files WHERE fk_owner = u.id),
+            u.used_bytes,
+            (SELECT COUNT(*) FROM storage_reservations WHERE fk_user = u.id)
+            FROM users u
+            WHERE u.id = $1;",
+        )
+        .bind(user_id)
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!(files, fitting as i64);
+        assert_eq!(used_bytes, (fitting * FILE_SIZE) as i64);
+        assert!(used_bytes <= QUOTA_MB * 1_000_000);
+        // The reservations were all used or released
+        assert_eq!(reserved, 0);
+    }
+}
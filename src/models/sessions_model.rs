@@ -1,5 +1,5 @@
 use crate::{errors::InternalError, models::RedisPool, SESSION_TTL};
-use bb8_redis::redis::{AsyncCommands, Expiry};
+use bb8_redis::redis::{self, AsyncCommands, Expiry};
 use rand_chacha::ChaCha8Rng;
 use rand_core::RngCore;
 
@@ -17,9 +17,16 @@ pub async fn new_session(
         .get()
         .await
         .map_err(|_| InternalError("Session error".to_string()))?;
-    // Save the session
-    let _: () = conn
-        .set_ex(&session_id.to_string(), user_id, *SESSION_TTL)
+    // Save the session, and add it to the sessions of the user
+    let _: () = redis::pipe()
+        .atomic()
+        .set_ex(session_id.to_string(), user_id, *SESSION_TTL)
+        .ignore()
+        .sadd(user_sessions_key(user_id), session_id.to_string())
+        .ignore()
+        .expire(user_sessions_key(user_id), *SESSION_TTL as i64)
+        .ignore()
+        .query_async(&mut *conn)
         .await
         .map_err(|_| InternalError("Session error".to_string()))?;
     Ok(session_id)
@@ -49,6 +56,16 @@ pub async fn get_session_user_id(
         .get_ex(session_id_str, Expiry::EX(*SESSION_TTL as usize))
         .await
         .map_err(|_| InternalError("Error while reading session".to_string()))?;
+    // The sessions of the user are kept as long as the session, which was just extended.
+    // Sessions created before they were indexed are added to them here.
+    let _: () = redis::pipe()
+        .sadd(user_sessions_key(user_id), session_id_str)
+        .ignore()
+        .expire(user_sessions_key(user_id), *SESSION_TTL as i64)
+        .ignore()
+        .query_async(&mut *conn)
+        .await
+        .map_err(|_| InternalError("Error while reading session".to_string()))?;
     Ok(Some(user_id))
 }
 
@@ -58,9 +75,57 @@ pub async fn delete_session(redis_pool: &RedisPool, session_id: u128) -> Result<
         .get()
         .await
         .map_err(|_| InternalError("Session error".to_string()))?;
+    let session_id_str = &session_id.to_string();
+    let user_id: Option<i32> = conn
+        .get(session_id_str)
+        .await
+        .map_err(|_| InternalError("Error while reading session".to_string()))?;
     // Delete the session
-    conn.del(session_id.to_string())
+    let _: () = conn
+        .del(session_id_str)
         .await
         .map_err(|_| InternalError("Error while deleting session".to_string()))?;
+    if let Some(user_id) = user_id {
+        let _: () = conn
+            .srem(user_sessions_key(user_id), session_id_str)
+            .await
+            .map_err(|_| InternalError("Error while deleting session".to_string()))?;
+    }
     Ok(())
 }
+
+/// Deletes all the sessions of the user, logging the user out everywhere.
+/// Returns the number of sessions that were deleted.
+pub async fn delete_user_sessions(
+    redis_pool: &RedisPool,
+    user_id: i32,
+) -> Result<usize, InternalError> {
+    // Connect to the Redis database
+    let mut conn = redis_pool
+        .get()
+        .await
+        .map_err(|_| InternalError("Session error".to_string()))?;
+    let key = user_sessions_key(user_id);
+    let session_ids: Vec<String> = conn
+        .smembers(&key)
+        .await
+        .map_err(|_| InternalError("Error while listing sessions".to_string()))?;
+    let mut count = 0;
+    for session_id in &session_ids {
+        // The sessions that expired are still listed
+        let deleted: usize = conn
+            .del(session_id)
+            .await
+            .map_err(|_| InternalError("Error while deleting session".to_string()))?;
+        count += deleted;
+    }
+    let _: () = conn
+        .del(&key)
+        .await
+        .map_err(|_| InternalError("Error while deleting session".to_string()))?;
+    Ok(count)
+}
+
+fn user_sessions_key(user_id: i32) -> String {
+    format!("user_sessions:{}", user_id)
+}
@@ -0,0 +1,30 @@
+#[derive(sqlx::FromRow)]
+pub struct Share {
+    pub(super) id: i32,
+    pub(super) fk_file: Option<i32>,
+    pub(super) fk_folder: Option<i32>,
+    pub(super) item_name: String,
+    pub(super) username: String,
+}
+
+impl Share {
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn get_fk_file(&self) -> &Option<i32> {
+        &self.fk_file
+    }
+
+    pub fn get_fk_folder(&self) -> &Option<i32> {
+        &self.fk_folder
+    }
+
+    pub fn get_item_name(&self) -> &String {
+        &self.item_name
+    }
+
+    pub fn get_username(&self) -> &String {
+        &self.username
+    }
+}
@@ -0,0 +1,174 @@
+use super::{file::File, folder::Folder, share::Share, users_model};
+use crate::errors::{InternalError, ShareError};
+use sqlx::PgPool;
+
+pub async fn share_file(
+    pg_pool: &PgPool,
+    file_id: i32,
+    owner_id: i32,
+    username: &str,
+) -> Result<(), ShareError> {
+    let user_id = get_recipient_id(pg_pool, owner_id, username).await?;
+    let res = sqlx::query!(
+        "INSERT INTO shares (fk_file, fk_owner, fk_user)
+        SELECT id, fk_owner, $3
+        FROM files
+        WHERE id = $1 AND fk_owner = $2
+        RETURNING id;",
+        file_id,
+        owner_id,
+        user_id
+    )
+    .fetch_optional(pg_pool)
+    .await;
+    handle_share_result(res)
+}
+
+pub async fn share_folder(
+    pg_pool: &PgPool,
+    folder_id: i32,
+    owner_id: i32,
+    username: &str,
+) -> Result<(), ShareError> {
+    let user_id = get_recipient_id(pg_pool, owner_id, username).await?;
+    // Root folders (My Cloud and Trash) can't be shared
+    let res = sqlx::query!(
+        "INSERT INTO shares (fk_folder, fk_owner, fk_user)
+        SELECT id, fk_owner, $3
+        FROM folders
+        WHERE id = $1 AND fk_owner = $2 AND fk_parent IS NOT null
+        RETURNING id;",
+        folder_id,
+        owner_id,
+        user_id
+    )
+    .fetch_optional(pg_pool)
+    .await;
+    handle_share_result(res)
+}
+
+pub async fn unshare_file(
+    pg_pool: &PgPool,
+    file_id: i32,
+    owner_id: i32,
+    username: &str,
+) -> Result<(), InternalError> {
+    sqlx::query!(
+        "DELETE FROM shares
+        WHERE fk_file = $1 AND fk_owner = $2
+        AND fk_user = (SELECT id FROM users WHERE username = $3);",
+        file_id,
+        owner_id,
+        username
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to unshare the file".to_string()))?;
+    Ok(())
+}
+
+pub async fn unshare_folder(
+    pg_pool: &PgPool,
+    folder_id: i32,
+    owner_id: i32,
+    username: &str,
+) -> Result<(), InternalError> {
+    sqlx::query!(
+        "DELETE FROM shares
+        WHERE fk_folder = $1 AND fk_owner = $2
+        AND fk_user = (SELECT id FROM users WHERE username = $3);",
+        folder_id,
+        owner_id,
+        username
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to unshare the folder".to_string()))?;
+    Ok(())
+}
+
+pub async fn get_shares(pg_pool: &PgPool, owner_id: i32) -> Result<Vec<Share>, InternalError> {
+    sqlx::query_as!(
+        Share,
+        r#"SELECT s.id, s.fk_file, s.fk_folder,
+        COALESCE(fi.name, fo.name) as "item_name!", u.username
+        FROM shares s
+        JOIN users u ON u.id = s.fk_user
+        LEFT JOIN files fi ON fi.id = s.fk_file
+        LEFT JOIN folders fo ON fo.id = s.fk_folder
+        WHERE s.fk_owner = $1
+        ORDER BY s.id;"#,
+        owner_id
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the shares".to_string()))
+}
+
+/// Returns the files shared with the user, except the ones in the trash of their owner.
+pub async fn get_shared_files(pg_pool: &PgPool, user_id: i32) -> Result<Vec<File>, InternalError> {
+    sqlx::query_as!(
+        File,
+        "SELECT f.id, f.name, f.file_type, f.mime_type, f.size, f.last_modified, f.starred,
+        f.fk_owner, f.fk_parent, f.fk_blob
+        FROM files f
+        JOIN shares s ON s.fk_file = f.id
+        WHERE s.fk_user = $1
+        AND f.fk_parent NOT IN (SELECT folder_id FROM get_trash_tree(f.fk_owner));",
+        user_id
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the shared files".to_string()))
+}
+
+/// Returns the folders shared with the user, except the ones in the trash of their owner.
+pub async fn get_shared_folders(
+    pg_pool: &PgPool,
+    user_id: i32,
+) -> Result<Vec<Folder>, InternalError> {
+    sqlx::query_as!(
+        Folder,
+        "SELECT f.*
+        FROM folders f
+        JOIN shares s ON s.fk_folder = f.id
+        WHERE s.fk_user = $1
+        AND f.id NOT IN (SELECT folder_id FROM get_trash_tree(f.fk_owner));",
+        user_id
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the shared folders".to_string()))
+}
+
+async fn get_recipient_id(
+    pg_pool: &PgPool,
+    owner_id: i32,
+    username: &str,
+) -> Result<i32, ShareError> {
+    let user = users_model::get_user_by_username(pg_pool, username)
+        .await
+        .map_err(|_| ShareError::InternalError)?
+        .ok_or(ShareError::UserNotFound)?;
+    if user.get_id() == owner_id {
+        return Err(ShareError::SelfShare);
+    }
+    Ok(user.get_id())
+}
+
+fn handle_share_result<T>(res: Result<Option<T>, sqlx::Error>) -> Result<(), ShareError> {
+    match res {
+        Ok(Some(_)) => Ok(()),
+        // Nothing was inserted, so the user doesn't own the file or folder
+        Ok(None) => Err(ShareError::NotFound),
+        Err(sqlx::Error::Database(db))
+            if matches!(
+                db.constraint(),
+                Some("shares_fk_file_fk_user_key" | "shares_fk_folder_fk_user_key")
+            ) =>
+        {
+            Err(ShareError::AlreadyShared)
+        }
+        Err(_) => Err(ShareError::InternalError),
+    }
+}
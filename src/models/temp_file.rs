@@ -0,0 +1,183 @@
+use super::FILES_FOLDER;
+use crate::errors::InternalError;
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Content that is being written to disk before it's associated to a file.
+pub struct TempFile {
+    path: PathBuf,
+    file: fs::File,
+    size: i64,
+    // Only known when all the content went through this instance
+    hasher: Option<Sha256>,
+}
+
+impl TempFile {
+    pub async fn create(id: u64) -> Result<Self, InternalError> {
+        Self::create_at(build_temp_path(id), id).await
+    }
+
+    /// Creates the temp file in the given folder instead of the folder of the files.
+    #[cfg(test)]
+    pub async fn create_in(folder: &Path, id: u64) -> Result<Self, InternalError> {
+        Self::create_at(folder.join(format!("{:016x}.part", id)), id).await
+    }
+
+    async fn create_at(path: PathBuf, id: u64) -> Result<Self, InternalError> {
+        let file = fs::File::create(&path)
+            .await
+            .map_err(|_| InternalError(format!("Failed to create temp file '{:016x}'", id)))?;
+        Ok(TempFile {
+            path,
+            file,
+            size: 0,
+            hasher: Some(Sha256::new()),
+        })
+    }
+
+    /// Opens an existing temp file to append more content to it.
+    pub async fn open(id: u64) -> Result<Self, InternalError> {
+        let path = build_temp_path(id);
+        let file = fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|_| InternalError(format!("Failed to open temp file '{:016x}'", id)))?;
+        let size = file
+            .metadata()
+            .await
+            .map_err(|_| InternalError(format!("Failed to open temp file '{:016x}'", id)))?
+            .len() as i64;
+        Ok(TempFile {
+            path,
+            file,
+            size,
+            hasher: None,
+        })
+    }
+
+    pub fn get_size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), InternalError> {
+        // The chunk must be on disk before it's acknowledged, so that it can be read back
+        let res = match self.file.write_all(chunk).await {
+            Ok(_) => self.file.flush().await,
+            Err(e) => Err(e),
+        };
+        res.map_err(|_| InternalError("Failed to write to temp file".to_string()))?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(chunk);
+        }
+        self.size += chunk.len() as i64;
+        Ok(())
+    }
+
+    /// Reads up to `len` bytes from the beginning of the content.
+    pub(super) async fn read_head(&self, len: usize) -> Result<Vec<u8>, InternalError> {
+        let err = |_| InternalError("Failed to read temp file".to_string());
+        let file = fs::File::open(&self.path).await.map_err(err)?;
+        let mut head = Vec::with_capacity(len);
+        file.take(len as u64)
+            .read_to_end(&mut head)
+            .await
+            .map_err(err)?;
+        Ok(head)
+    }
+
+    /// Deletes the temp file.
+    pub async fn discard(self) {
+        drop(self.file);
+        // If it can't be deleted there isn't much that can be done
+        let _ = fs::remove_file(self.path).await;
+    }
+
+    /// Returns the SHA-256 of the content as a hex string.
+    /// Content that was appended over several requests is read back from the disk.
+    pub(super) async fn hash(&self) -> Result<String, InternalError> {
+        let digest = match &self.hasher {
+            Some(hasher) => hasher.clone().finalize(),
+            None => hash_from_disk(&self.path)
+                .await
+                .map_err(|_| InternalError("Failed to hash temp file".to_string()))?,
+        };
+        Ok(format!("{:x}", digest))
+    }
+
+    /// Moves the content to the given path, replacing what's there.
+    /// The temp file is deleted if that's not possible.
+    pub async fn persist(self, path: &Path) -> Result<(), InternalError> {
+        let res = match self.file.sync_all().await {
+            Ok(_) => fs::rename(&self.path, path).await,
+            Err(e) => Err(e),
+        };
+        if res.is_err() {
+            self.discard().await;
+            return Err(InternalError(format!(
+                "Failed to move temp file to '{}'",
+                path.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the ids of the temp files that haven't been written to for the given time.
+pub(super) async fn get_stale_temp_files(max_age: Duration) -> Result<Vec<u64>, InternalError> {
+    let err = |_| InternalError("Failed to list the temp files".to_string());
+    let mut ids = Vec::new();
+    let mut entries = fs::read_dir(FILES_FOLDER).await.map_err(err)?;
+    while let Some(entry) = entries.next_entry().await.map_err(err)? {
+        let name = entry.file_name();
+        let Some(id) = name
+            .to_str()
+            .and_then(|n| n.strip_suffix(".part"))
+            .and_then(|n| u64::from_str_radix(n, 16).ok())
+        else {
+            continue;
+        };
+        let age = entry
+            .metadata()
+            .await
+            .and_then(|m| m.modified())
+            .map(|t| SystemTime::now().duration_since(t).unwrap_or_default());
+        if age.is_ok_and(|a| a > max_age) {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+async fn hash_from_disk(path: &Path) -> std::io::Result<sha2::digest::Output<Sha256>> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize())
+}
+
+fn build_temp_path(id: u64) -> PathBuf {
+    let mut path = PathBuf::from(FILES_FOLDER);
+    path.push(format!("{:016x}.part", id));
+    path
+}
@@ -0,0 +1,19 @@
+/// A smaller copy of an image, made once for each size that's asked for.
+/// It belongs to the blob of the image, so it's shared by the files with the same content.
+#[derive(sqlx::FromRow)]
+pub struct Thumbnail {
+    pub(super) fk_blob: String,
+    pub(super) size: i32,
+    pub(super) mime_type: String,
+    pub(super) bytes: i32,
+}
+
+impl Thumbnail {
+    pub fn get_mime_type(&self) -> &String {
+        &self.mime_type
+    }
+
+    pub fn get_bytes(&self) -> i32 {
+        self.bytes
+    }
+}
@@ -0,0 +1,183 @@
+use super::{file::File, thumbnail::Thumbnail, TempFile};
+use crate::{
+    errors::{InternalError, ThumbnailError},
+    storage::{ContentReader, StorageBackend},
+};
+use image::{imageops::FilterType, DynamicImage, ImageOutputFormat};
+use sqlx::{PgConnection, PgPool};
+use std::io::Cursor;
+use tokio::io::AsyncReadExt;
+
+/// The images that thumbnails can be made of.
+const SOURCE_MIME_TYPES: [&str; 5] = [
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/bmp",
+];
+// The whole image is decoded in memory
+const MAX_SOURCE_BYTES: i64 = 50_000_000;
+const JPEG_QUALITY: u8 = 80;
+
+/// Returns the thumbnail of the image that fits in a square of the given size, with its content.
+/// The thumbnail is made the first time it's asked for, then kept until the content of the image
+/// is deleted.
+pub async fn get_thumbnail(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    file: &File,
+    size: u32,
+    temp_id: u64,
+) -> Result<(Thumbnail, ContentReader), ThumbnailError> {
+    if !SOURCE_MIME_TYPES.contains(&file.mime_type.as_str()) || file.size > MAX_SOURCE_BYTES {
+        return Err(ThumbnailError::Unsupported);
+    }
+    let existing = sqlx::query_as!(
+        Thumbnail,
+        "SELECT *
+        FROM thumbnails
+        WHERE fk_blob = $1 AND size = $2;",
+        file.fk_blob,
+        size as i32
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .map_err(|_| ThumbnailError::InternalError)?;
+    if let Some(thumbnail) = existing {
+        // Made again if its content is missing
+        if let Ok(content) = storage.get(&thumbnail_key(&thumbnail), None).await {
+            return Ok((thumbnail, content));
+        }
+    }
+    let mut image = Vec::with_capacity(file.size as usize);
+    storage
+        .get(&file.fk_blob, None)
+        .await
+        .map_err(|_| ThumbnailError::InternalError)?
+        .read_to_end(&mut image)
+        .await
+        .map_err(|_| ThumbnailError::InternalError)?;
+    let jpeg = file.mime_type == "image/jpeg";
+    let data = tokio::task::spawn_blocking(move || make_thumbnail(&image, size, jpeg))
+        .await
+        .map_err(|_| ThumbnailError::InternalError)?
+        .ok_or(ThumbnailError::Unsupported)?;
+    let thumbnail = store_thumbnail(pg_pool, storage, &file.fk_blob, size, jpeg, &data, temp_id)
+        .await
+        .map_err(|_| ThumbnailError::InternalError)?;
+    Ok((thumbnail, Box::pin(Cursor::new(data))))
+}
+
+/// Returns the storage keys of all the thumbnails.
+pub async fn get_thumbnail_keys(pg_pool: &PgPool) -> Result<Vec<String>, InternalError> {
+    let thumbnails = sqlx::query_as!(
+        Thumbnail,
+        "SELECT *
+        FROM thumbnails;"
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the thumbnails".to_string()))?;
+    Ok(thumbnails.iter().map(thumbnail_key).collect())
+}
+
+/// Deletes the content of the thumbnails of the blob. Their rows go away with the blob.
+pub(super) async fn delete_thumbnails(
+    conn: &mut PgConnection,
+    storage: &dyn StorageBackend,
+    hash: &str,
+) -> Result<(), InternalError> {
+    let thumbnails = sqlx::query_as!(
+        Thumbnail,
+        "SELECT *
+        FROM thumbnails
+        WHERE fk_blob = $1;",
+        hash
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(|_| InternalError(format!("Failed to get the thumbnails of blob '{}'", hash)))?;
+    for thumbnail in &thumbnails {
+        storage.delete(&thumbnail_key(thumbnail)).await?;
+    }
+    Ok(())
+}
+
+/// Records the thumbnail and writes its content. The row is only committed once the content is
+/// there, and it keeps the blob from being purged in the meantime.
+async fn store_thumbnail(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    hash: &str,
+    size: u32,
+    jpeg: bool,
+    data: &[u8],
+    temp_id: u64,
+) -> Result<Thumbnail, InternalError> {
+    let err = || InternalError(format!("Failed to store a thumbnail of blob '{}'", hash));
+    let mut content = TempFile::create(temp_id).await?;
+    if let Err(e) = content.write_chunk(data).await {
+        content.discard().await;
+        return Err(e);
+    }
+    let mime_type = if jpeg { "image/jpeg" } else { "image/png" };
+    let mut tx = match pg_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => {
+            content.discard().await;
+            return Err(err());
+        }
+    };
+    // Fails if the blob was purged since the file was read
+    let thumbnail = sqlx::query_as!(
+        Thumbnail,
+        "INSERT INTO thumbnails (fk_blob, size, mime_type, bytes)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (fk_blob, size) DO UPDATE
+        SET mime_type = EXCLUDED.mime_type, bytes = EXCLUDED.bytes
+        RETURNING *;",
+        hash,
+        size as i32,
+        mime_type,
+        data.len() as i32
+    )
+    .fetch_one(&mut *tx)
+    .await;
+    let thumbnail = match thumbnail {
+        Ok(thumbnail) => thumbnail,
+        Err(_) => {
+            content.discard().await;
+            return Err(err());
+        }
+    };
+    storage.put(&thumbnail_key(&thumbnail), content).await?;
+    tx.commit().await.map_err(|_| err())?;
+    Ok(thumbnail)
+}
+
+/// Decodes the image and scales it down to fit in the size, keeping its proportions.
+/// Photos are encoded as JPEG, the rest as PNG to keep the transparency.
+/// Returns None if the image can't be decoded.
+fn make_thumbnail(image: &[u8], size: u32, jpeg: bool) -> Option<Vec<u8>> {
+    let image = image::load_from_memory(image).ok()?;
+    // Small images aren't made larger
+    let image = if image.width() > size || image.height() > size {
+        image.resize(size, size, FilterType::Triangle)
+    } else {
+        image
+    };
+    let mut data = Cursor::new(Vec::new());
+    let res = if jpeg {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(&mut data, ImageOutputFormat::Jpeg(JPEG_QUALITY))
+    } else {
+        image.write_to(&mut data, ImageOutputFormat::Png)
+    };
+    res.ok()?;
+    Some(data.into_inner())
+}
+
+fn thumbnail_key(thumbnail: &Thumbnail) -> String {
+    format!("{}.{}px", thumbnail.fk_blob, thumbnail.size)
+}
@@ -0,0 +1,356 @@
+use super::{files_model, folders_model};
+use crate::{
+    errors::{InternalError, TrashError},
+    storage::StorageBackend,
+};
+use sqlx::{PgConnection, PgPool};
+
+pub async fn trash_file(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    file_id: i32,
+    owner_id: i32,
+) -> Result<(), TrashError> {
+    let file = files_model::find_file(pg_pool, file_id, owner_id)
+        .await
+        .map_err(|_| TrashError::InternalError)?
+        .ok_or(TrashError::NotFound)?;
+    // Files that are already in the trash get deleted permanently
+    if is_in_trash(pg_pool, file.get_fk_parent()).await? {
+        return files_model::delete_file(pg_pool, storage, file_id, owner_id)
+            .await
+            .map_err(|_| TrashError::InternalError);
+    }
+    let trash = folders_model::get_trash_folder(pg_pool, owner_id)
+        .await
+        .map_err(|_| TrashError::InternalError)?;
+    let mut tx = pg_pool
+        .begin()
+        .await
+        .map_err(|_| TrashError::InternalError)?;
+    // Remember where the file was, then move it in the trash
+    sqlx::query!(
+        "DELETE FROM trash
+        WHERE fk_file = $1;",
+        file_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    sqlx::query!(
+        "INSERT INTO trash (fk_file, fk_owner, fk_original_parent, original_path, trashed_at)
+        VALUES ($1, $2, $3, ARRAY(SELECT name FROM get_folder_path($3) ORDER BY depth DESC OFFSET 1),
+        CURRENT_TIMESTAMP);",
+        file_id,
+        owner_id,
+        file.get_fk_parent()
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    sqlx::query!(
+        "UPDATE files
+        SET fk_parent = $3
+        WHERE id = $1 AND fk_owner = $2;",
+        file_id,
+        owner_id,
+        trash.get_id()
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    tx.commit().await.map_err(|_| TrashError::InternalError)
+}
+
+pub async fn trash_folder(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    folder_id: i32,
+    owner_id: i32,
+    preserve_parent: bool,
+) -> Result<(), TrashError> {
+    let folder = folders_model::find_folder(pg_pool, folder_id, owner_id)
+        .await
+        .map_err(|_| TrashError::InternalError)?
+        .ok_or(TrashError::NotFound)?;
+    // Neither the personal folder nor the trash itself can be deleted
+    let Some(parent_id) = *folder.get_fk_parent() else {
+        return Err(TrashError::RootFolder);
+    };
+    // Folders that are already in the trash get deleted permanently
+    if is_in_trash(pg_pool, folder_id).await? {
+        return folders_model::delete_folder(
+            pg_pool,
+            storage,
+            folder_id,
+            owner_id,
+            preserve_parent,
+        )
+        .await
+        .map_err(|_| TrashError::InternalError);
+    }
+    // Only the content of the folders in the trash can be deleted
+    if preserve_parent {
+        return Err(TrashError::NotInTrash);
+    }
+    let trash = folders_model::get_trash_folder(pg_pool, owner_id)
+        .await
+        .map_err(|_| TrashError::InternalError)?;
+    let mut tx = pg_pool
+        .begin()
+        .await
+        .map_err(|_| TrashError::InternalError)?;
+    // Remember where the folder was, then move it in the trash
+    sqlx::query!(
+        "DELETE FROM trash
+        WHERE fk_folder = $1;",
+        folder_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    sqlx::query!(
+        "INSERT INTO trash (fk_folder, fk_owner, fk_original_parent, original_path, trashed_at)
+        VALUES ($1, $2, $3, ARRAY(SELECT name FROM get_folder_path($3) ORDER BY depth DESC OFFSET 1),
+        CURRENT_TIMESTAMP);",
+        folder_id,
+        owner_id,
+        parent_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    sqlx::query!(
+        "UPDATE folders
+        SET fk_parent = $3
+        WHERE id = $1 AND fk_owner = $2;",
+        folder_id,
+        owner_id,
+        trash.get_id()
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    tx.commit().await.map_err(|_| TrashError::InternalError)
+}
+
+pub async fn restore_file(pg_pool: &PgPool, file_id: i32, owner_id: i32) -> Result<(), TrashError> {
+    let file = files_model::find_file(pg_pool, file_id, owner_id)
+        .await
+        .map_err(|_| TrashError::InternalError)?
+        .ok_or(TrashError::NotFound)?;
+    if !is_in_trash(pg_pool, file.get_fk_parent()).await? {
+        return Err(TrashError::NotInTrash);
+    }
+    let mut tx = pg_pool
+        .begin()
+        .await
+        .map_err(|_| TrashError::InternalError)?;
+    let entry = sqlx::query!(
+        "DELETE FROM trash
+        WHERE fk_file = $1
+        RETURNING fk_original_parent, original_path;",
+        file_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    let (original_parent, original_path) = entry
+        .map(|e| (e.fk_original_parent, e.original_path))
+        .unwrap_or_default();
+    let to_folder_id = get_restore_folder(&mut tx, owner_id, original_parent, &original_path)
+        .await
+        .map_err(|_| TrashError::InternalError)?;
+    sqlx::query!(
+        "UPDATE files
+        SET fk_parent = $3
+        WHERE id = $1 AND fk_owner = $2;",
+        file_id,
+        owner_id,
+        to_folder_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    tx.commit().await.map_err(|_| TrashError::InternalError)
+}
+
+pub async fn restore_folder(
+    pg_pool: &PgPool,
+    folder_id: i32,
+    owner_id: i32,
+) -> Result<(), TrashError> {
+    let folder = folders_model::find_folder(pg_pool, folder_id, owner_id)
+        .await
+        .map_err(|_| TrashError::InternalError)?
+        .ok_or(TrashError::NotFound)?;
+    // The trash itself can't be restored
+    if folder.get_fk_parent().is_none() || !is_in_trash(pg_pool, folder_id).await? {
+        return Err(TrashError::NotInTrash);
+    }
+    let mut tx = pg_pool
+        .begin()
+        .await
+        .map_err(|_| TrashError::InternalError)?;
+    let entry = sqlx::query!(
+        "DELETE FROM trash
+        WHERE fk_folder = $1
+        RETURNING fk_original_parent, original_path;",
+        folder_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    let (original_parent, original_path) = entry
+        .map(|e| (e.fk_original_parent, e.original_path))
+        .unwrap_or_default();
+    let to_folder_id = get_restore_folder(&mut tx, owner_id, original_parent, &original_path)
+        .await
+        .map_err(|_| TrashError::InternalError)?;
+    sqlx::query!(
+        "UPDATE folders
+        SET fk_parent = $3
+        WHERE id = $1 AND fk_owner = $2;",
+        folder_id,
+        owner_id,
+        to_folder_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| TrashError::InternalError)?;
+    tx.commit().await.map_err(|_| TrashError::InternalError)
+}
+
+pub async fn empty_trash(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    owner_id: i32,
+) -> Result<(), InternalError> {
+    let trash = folders_model::get_trash_folder(pg_pool, owner_id).await?;
+    folders_model::delete_folder(pg_pool, storage, trash.get_id(), owner_id, true).await
+}
+
+/// Permanently deletes the items that have been in the trash for longer than the given days.
+/// Returns the number of bytes that were freed. The items that can't be deleted are reported and
+/// skipped, they are tried again the next time.
+pub async fn purge_expired(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    retention_days: i32,
+) -> Result<i64, InternalError> {
+    // Items that were moved out of the trash without restoring them are skipped
+    let expired = sqlx::query!(
+        "SELECT fk_file, fk_folder, fk_owner
+        FROM trash
+        WHERE trashed_at < CURRENT_TIMESTAMP - make_interval(days => $1)
+        AND is_in_trash(COALESCE((SELECT fk_parent FROM files WHERE id = fk_file), fk_folder));",
+        retention_days
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the expired trash items".to_string()))?;
+    let mut purged_bytes = 0;
+    for item in expired {
+        let res = match (item.fk_file, item.fk_folder) {
+            (Some(file_id), _) => purge_file(pg_pool, storage, file_id, item.fk_owner).await,
+            (None, Some(folder_id)) => {
+                purge_folder(pg_pool, storage, folder_id, item.fk_owner).await
+            }
+            (None, None) => Ok(0),
+        };
+        match res {
+            Ok(bytes) => purged_bytes += bytes,
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    Ok(purged_bytes)
+}
+
+/// Permanently deletes the file, returning its size.
+async fn purge_file(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    file_id: i32,
+    owner_id: i32,
+) -> Result<i64, InternalError> {
+    let file = files_model::get_file_by_id(pg_pool, file_id, owner_id).await?;
+    files_model::delete_file(pg_pool, storage, file_id, owner_id).await?;
+    Ok(file.get_size())
+}
+
+/// Permanently deletes the folder and its content, returning their size.
+async fn purge_folder(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    folder_id: i32,
+    owner_id: i32,
+) -> Result<i64, InternalError> {
+    let size = folders_model::folder_size(pg_pool, folder_id, owner_id, None).await?;
+    folders_model::delete_folder(pg_pool, storage, folder_id, owner_id, false).await?;
+    Ok(size)
+}
+
+async fn is_in_trash(pg_pool: &PgPool, folder_id: i32) -> Result<bool, TrashError> {
+    sqlx::query!(r#"SELECT is_in_trash($1) as "in_trash!";"#, folder_id)
+        .fetch_one(pg_pool)
+        .await
+        .map(|r| r.in_trash)
+        .map_err(|_| TrashError::InternalError)
+}
+
+/// Returns the folder an item should be restored to.
+/// If the original parent is gone (or in the trash), its path gets recreated in "My Cloud".
+async fn get_restore_folder(
+    conn: &mut PgConnection,
+    owner_id: i32,
+    original_parent: Option<i32>,
+    original_path: &[String],
+) -> Result<i32, sqlx::Error> {
+    if let Some(parent_id) = original_parent {
+        let in_trash = sqlx::query!(r#"SELECT is_in_trash($1) as "in_trash!";"#, parent_id)
+            .fetch_one(&mut *conn)
+            .await?
+            .in_trash;
+        if !in_trash {
+            return Ok(parent_id);
+        }
+    }
+    let mut folder_id = sqlx::query!(
+        "SELECT id FROM folders
+        WHERE fk_owner = $1 AND fk_parent IS null AND name = 'My Cloud';",
+        owner_id
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .id;
+    for name in original_path {
+        let existing = sqlx::query!(
+            "SELECT id FROM folders
+            WHERE fk_owner = $1 AND fk_parent = $2 AND name = $3
+            ORDER BY id
+            LIMIT 1;",
+            owner_id,
+            folder_id,
+            name
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        folder_id = match existing {
+            Some(f) => f.id,
+            None => {
+                sqlx::query!(
+                    "INSERT INTO folders (name, last_modified, starred, fk_owner, fk_parent)
+                    VALUES ($1, CURRENT_TIMESTAMP, false, $2, $3)
+                    RETURNING id;",
+                    name,
+                    owner_id,
+                    folder_id
+                )
+                .fetch_one(&mut *conn)
+                .await?
+                .id
+            }
+        };
+    }
+    Ok(folder_id)
+}
@@ -0,0 +1,67 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+#[derive(sqlx::FromRow)]
+pub struct UploadSession {
+    pub(super) id: String,
+    pub(super) name: String,
+    pub(super) size: i64,
+    pub(super) fk_owner: i32,
+    pub(super) fk_parent: i32,
+}
+
+impl UploadSession {
+    pub fn get_id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn get_fk_owner(&self) -> i32 {
+        self.fk_owner
+    }
+
+    pub fn get_fk_parent(&self) -> i32 {
+        self.fk_parent
+    }
+}
+
+/// The sessions whose chunks are being received, shared by all the requests.
+/// The content of the sessions is on the local disk, so they only need to be locked here.
+#[derive(Clone, Default)]
+pub struct SessionLocks(Arc<Mutex<HashSet<String>>>);
+
+impl SessionLocks {
+    /// Returns None if the session is already being used by another request.
+    pub fn try_lock(&self, session: &UploadSession) -> Option<SessionLock> {
+        let mut locked = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if !locked.insert(session.id.clone()) {
+            return None;
+        }
+        Some(SessionLock {
+            locks: self.clone(),
+            session_id: session.id.clone(),
+        })
+    }
+}
+
+/// Keeps the other requests from using the session until it's dropped.
+pub struct SessionLock {
+    locks: SessionLocks,
+    session_id: String,
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        let mut locked = self.locks.0.lock().unwrap_or_else(|e| e.into_inner());
+        locked.remove(&self.session_id);
+    }
+}
@@ -0,0 +1,181 @@
+use super::{
+    blobs_model, file::File, files_model, files_model::validate_name,
+    temp_file::get_stale_temp_files, upload_session::UploadSession, Reservation, TempFile,
+};
+use crate::{
+    errors::{FileError, InternalError},
+    storage::StorageBackend,
+};
+use sqlx::{PgConnection, PgPool};
+use std::time::Duration;
+
+// Sessions started before that are considered abandoned
+const SESSION_TTL_SECS: i64 = 24 * 60 * 60;
+
+pub async fn new_session(
+    pg_pool: &PgPool,
+    id: u64,
+    file_name: &str,
+    file_size: i64,
+    parent_folder_id: i32,
+    owner_id: i32,
+) -> Result<UploadSession, FileError> {
+    if !validate_name(file_name) {
+        return Err(FileError::NameError);
+    }
+    // The session can only be created in a folder owned by the user
+    let session = sqlx::query_as!(
+        UploadSession,
+        "INSERT INTO upload_sessions (id, name, size, created_at, fk_owner, fk_parent)
+        SELECT $1, $2, $3, CURRENT_TIMESTAMP, fk_owner, id
+        FROM folders
+        WHERE id = $4 AND fk_owner = $5
+        RETURNING id, name, size, fk_owner, fk_parent;",
+        format!("{:016x}", id),
+        file_name,
+        file_size,
+        parent_folder_id,
+        owner_id
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .map_err(|_| FileError::InternalError)?
+    .ok_or(FileError::NotFound)?;
+    // Create the empty file where the chunks will be appended
+    TempFile::create(id)
+        .await
+        .map_err(|_| FileError::InternalError)?;
+    Ok(session)
+}
+
+pub async fn get_session(
+    pg_pool: &PgPool,
+    session_id: &str,
+    owner_id: i32,
+) -> Result<UploadSession, InternalError> {
+    sqlx::query_as!(
+        UploadSession,
+        "SELECT id, name, size, fk_owner, fk_parent
+        FROM upload_sessions
+        WHERE id = $1 AND fk_owner = $2;",
+        session_id,
+        owner_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the upload session".to_string()))
+}
+
+/// Opens the content received so far, so that more chunks can be appended to it.
+pub async fn open_session_content(session: &UploadSession) -> Result<TempFile, InternalError> {
+    TempFile::open(parse_session_id(session)?).await
+}
+
+/// Turns the content received in the session into a new file.
+pub async fn finish_session(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    session: &UploadSession,
+    content: TempFile,
+    reservation: &Reservation,
+) -> Result<File, FileError> {
+    let (mut tx, file, hash) = files_model::prepare_file(
+        pg_pool,
+        storage,
+        session.get_name(),
+        content,
+        session.get_fk_parent(),
+        session.get_fk_owner(),
+        reservation,
+    )
+    .await?;
+    // The session goes away with the file being added, so that it's finished only once
+    if let Ok(true) = delete_session_row(&mut tx, session).await {
+        if tx.commit().await.is_ok() {
+            return Ok(file);
+        }
+    } else {
+        let _ = tx.rollback().await;
+    }
+    let _ = blobs_model::purge_blobs(pg_pool, storage, &[hash]).await;
+    Err(FileError::InternalError)
+}
+
+pub async fn cancel_session(
+    pg_pool: &PgPool,
+    session: &UploadSession,
+) -> Result<(), InternalError> {
+    let err = |_| InternalError("Failed to delete the upload session".to_string());
+    let mut conn = pg_pool.acquire().await.map_err(err)?;
+    delete_session_row(&mut conn, session).await.map_err(err)?;
+    open_session_content(session).await?.discard().await;
+    Ok(())
+}
+
+/// Deletes the sessions that were abandoned, along with the content they received, and the
+/// content left behind by the sessions that went away with their folder or user.
+/// Returns the number of sessions that were deleted.
+pub async fn purge_expired(pg_pool: &PgPool) -> Result<usize, InternalError> {
+    let sessions = sqlx::query_as!(
+        UploadSession,
+        "DELETE FROM upload_sessions
+        WHERE created_at <= CURRENT_TIMESTAMP - make_interval(secs => $1)
+        RETURNING id, name, size, fk_owner, fk_parent;",
+        SESSION_TTL_SECS as f64
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to purge the upload sessions".to_string()))?;
+    for session in &sessions {
+        // The content may not have been created
+        if let Ok(content) = open_session_content(session).await {
+            content.discard().await;
+        }
+    }
+    // The temp files of the other uploads are used for less time than a session lasts
+    let stale_ids = get_stale_temp_files(Duration::from_secs(SESSION_TTL_SECS as u64)).await?;
+    let session_ids: Vec<String> = stale_ids.iter().map(|id| format!("{:016x}", id)).collect();
+    let live_ids: Vec<String> = sqlx::query!(
+        "SELECT id
+        FROM upload_sessions
+        WHERE id = ANY($1);",
+        &session_ids
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to purge the upload sessions".to_string()))?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+    let mut orphans = 0;
+    for (id, session_id) in stale_ids.into_iter().zip(&session_ids) {
+        if live_ids.contains(session_id) {
+            continue;
+        }
+        if let Ok(content) = TempFile::open(id).await {
+            content.discard().await;
+            orphans += 1;
+        }
+    }
+    Ok(sessions.len() + orphans)
+}
+
+/// Returns false if the session was already deleted.
+async fn delete_session_row(
+    conn: &mut PgConnection,
+    session: &UploadSession,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        "DELETE FROM upload_sessions
+        WHERE id = $1;",
+        session.get_id()
+    )
+    .execute(conn)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+fn parse_session_id(session: &UploadSession) -> Result<u64, InternalError> {
+    u64::from_str_radix(session.get_id(), 16)
+        .map_err(|_| InternalError("Invalid upload session id".to_string()))
+}
@@ -1,15 +1,20 @@
+use crate::MAX_STORAGE_MB;
+
 #[derive(sqlx::FromRow)]
 pub struct User {
     pub(super) id: i32,
     pub(super) username: String,
     pub(super) email: String,
     pub(super) password: String,
+    pub(super) max_storage_mb: Option<i64>,
+    pub(super) plan: Option<String>,
+    pub(super) plan_max_storage_mb: Option<i64>,
 }
 
 impl User {
-    // pub fn get_id(&self) -> i32 {
-    //     self.id
-    // }
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
 
     pub fn get_username(&self) -> &String {
         &self.username
@@ -19,7 +24,49 @@ impl User {
         &self.email
     }
 
+    /// The storage quota of the user, or else the one of the plan, or else the default one.
+    pub fn get_max_storage_mb(&self) -> i64 {
+        self.max_storage_mb
+            .or(self.plan_max_storage_mb)
+            .unwrap_or(*MAX_STORAGE_MB)
+    }
+
+    pub fn get_plan(&self) -> &Option<String> {
+        &self.plan
+    }
+
+    pub fn has_own_quota(&self) -> bool {
+        self.max_storage_mb.is_some()
+    }
+
     // fn get_password(&self) -> &String {
     //     &self.password
     // }
 }
+
+/// A user whose count of used bytes isn't the size of their files.
+#[derive(sqlx::FromRow)]
+pub struct WrongUsage {
+    pub(super) user_id: i32,
+    pub(super) username: String,
+    pub(super) used_bytes: i64,
+    pub(super) actual_bytes: i64,
+}
+
+impl WrongUsage {
+    pub fn get_user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    pub fn get_username(&self) -> &String {
+        &self.username
+    }
+
+    pub fn get_used_bytes(&self) -> i64 {
+        self.used_bytes
+    }
+
+    pub fn get_actual_bytes(&self) -> i64 {
+        self.actual_bytes
+    }
+}
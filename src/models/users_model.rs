@@ -1,5 +1,9 @@
-use super::{files_model, folders_model, User};
-use crate::errors::{InternalError, LoginError, SignupError};
+use super::{blobs_model, files_model, folders_model, User, WrongUsage};
+use crate::{
+    errors::{InternalError, LoginError, SignupError},
+    storage::StorageBackend,
+    PHYSICAL_QUOTA,
+};
 use bcrypt;
 use email_address::EmailAddress;
 use sqlx::PgPool;
@@ -21,12 +25,11 @@ pub async fn new_user(
         return Err(SignupError::ShortPassword);
     }
     let hashed_psw =
-        bcrypt::hash(&password, bcrypt::DEFAULT_COST).map_err(|_| SignupError::InternalError)?;
-    let res = sqlx::query_as!(
-        User,
+        bcrypt::hash(password, bcrypt::DEFAULT_COST).map_err(|_| SignupError::InternalError)?;
+    let res = sqlx::query!(
         "INSERT INTO users (username, email, password)
         VALUES ($1, $2, $3)
-        RETURNING *;",
+        RETURNING id;",
         username,
         email,
         hashed_psw
@@ -53,9 +56,11 @@ pub async fn new_user(
 pub async fn get_user_by_id(pg_pool: &PgPool, user_id: i32) -> Result<Option<User>, InternalError> {
     sqlx::query_as!(
         User,
-        "SELECT *
-        FROM users
-        WHERE id = $1;",
+        r#"SELECT u.id, u.username, u.email, u.password, u.max_storage_mb,
+        p.name as "plan?", p.max_storage_mb as "plan_max_storage_mb?"
+        FROM users u
+        LEFT JOIN plans p ON p.id = u.fk_plan
+        WHERE u.id = $1;"#,
         user_id
     )
     .fetch_optional(pg_pool)
@@ -70,9 +75,11 @@ pub async fn get_user_by_email(
     let email = email.to_lowercase();
     sqlx::query_as!(
         User,
-        "SELECT *
-        FROM users
-        WHERE email = $1;",
+        r#"SELECT u.id, u.username, u.email, u.password, u.max_storage_mb,
+        p.name as "plan?", p.max_storage_mb as "plan_max_storage_mb?"
+        FROM users u
+        LEFT JOIN plans p ON p.id = u.fk_plan
+        WHERE u.email = $1;"#,
         email
     )
     .fetch_optional(pg_pool)
@@ -80,6 +87,38 @@ pub async fn get_user_by_email(
     .map_err(|_| InternalError("Error while fetching user".to_string()))
 }
 
+pub async fn get_user_by_username(
+    pg_pool: &PgPool,
+    username: &str,
+) -> Result<Option<User>, InternalError> {
+    sqlx::query_as!(
+        User,
+        r#"SELECT u.id, u.username, u.email, u.password, u.max_storage_mb,
+        p.name as "plan?", p.max_storage_mb as "plan_max_storage_mb?"
+        FROM users u
+        LEFT JOIN plans p ON p.id = u.fk_plan
+        WHERE u.username = $1;"#,
+        username
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .map_err(|_| InternalError("Error while fetching user".to_string()))
+}
+
+pub async fn get_users(pg_pool: &PgPool) -> Result<Vec<User>, InternalError> {
+    sqlx::query_as!(
+        User,
+        r#"SELECT u.id, u.username, u.email, u.password, u.max_storage_mb,
+        p.name as "plan?", p.max_storage_mb as "plan_max_storage_mb?"
+        FROM users u
+        LEFT JOIN plans p ON p.id = u.fk_plan
+        ORDER BY u.id;"#
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Error while fetching users".to_string()))
+}
+
 pub async fn verify_user(pg_pool: &PgPool, email: &str, password: &str) -> Result<i32, LoginError> {
     let user = get_user_by_email(pg_pool, email)
         .await
@@ -94,18 +133,171 @@ pub async fn verify_user(pg_pool: &PgPool, email: &str, password: &str) -> Resul
     }
 }
 
-pub async fn delete_user(pg_pool: &PgPool, user_id: i32) -> Result<(), InternalError> {
-    files_model::delete_user_files(pg_pool, user_id).await?;
-    folders_model::delete_user_folders(pg_pool, user_id).await?;
+/// Replaces the password of the user. The sessions of the user are left as they are.
+pub async fn set_password(
+    pg_pool: &PgPool,
+    user_id: i32,
+    password: &str,
+) -> Result<(), SignupError> {
+    if !validate_password(password) {
+        return Err(SignupError::ShortPassword);
+    }
+    let hashed_psw =
+        bcrypt::hash(password, bcrypt::DEFAULT_COST).map_err(|_| SignupError::InternalError)?;
+    sqlx::query!(
+        "UPDATE users
+        SET password = $1
+        WHERE id = $2;",
+        hashed_psw,
+        user_id
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| SignupError::InternalError)?;
+    Ok(())
+}
+
+/// Puts the user on the plan, or on none of them.
+pub async fn set_plan(
+    pg_pool: &PgPool,
+    user_id: i32,
+    plan_id: Option<i32>,
+) -> Result<(), InternalError> {
+    sqlx::query!(
+        "UPDATE users
+        SET fk_plan = $1
+        WHERE id = $2;",
+        plan_id,
+        user_id
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError("Error while setting the plan".to_string()))?;
+    Ok(())
+}
+
+/// Sets the storage quota of the user, going back to the one of the plan when there's none.
+pub async fn set_max_storage(
+    pg_pool: &PgPool,
+    user_id: i32,
+    max_storage_mb: Option<i64>,
+) -> Result<(), InternalError> {
+    sqlx::query!(
+        "UPDATE users
+        SET max_storage_mb = $1
+        WHERE id = $2;",
+        max_storage_mb,
+        user_id
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|_| InternalError("Error while setting the quota".to_string()))?;
+    Ok(())
+}
+
+/// Returns the number of bytes that count against the storage quota of the user.
+pub async fn get_used_storage(pg_pool: &PgPool, user_id: i32) -> Result<i64, InternalError> {
+    if *PHYSICAL_QUOTA {
+        // Content shared by several files only counts once
+        let mut conn = pg_pool
+            .acquire()
+            .await
+            .map_err(|_| InternalError("Failed to get the used storage".to_string()))?;
+        return blobs_model::get_physical_usage(&mut conn, user_id).await;
+    }
+    // The previous versions of the files count too, the database keeps the count up to date
+    sqlx::query!(
+        "SELECT used_bytes
+        FROM users
+        WHERE id = $1;",
+        user_id
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the used storage".to_string()))?
+    .map(|r| r.used_bytes)
+    .ok_or_else(|| InternalError("User not found".to_string()))
+}
+
+/// Returns the users whose count of used bytes is wrong.
+pub async fn get_wrong_usages(pg_pool: &PgPool) -> Result<Vec<WrongUsage>, InternalError> {
+    sqlx::query_as!(
+        WrongUsage,
+        r#"SELECT id as user_id, username, used_bytes, actual_bytes as "actual_bytes!"
+        FROM (SELECT *, get_used_bytes(id) as actual_bytes FROM users) u
+        WHERE used_bytes <> actual_bytes
+        ORDER BY id;"#
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the used storage".to_string()))
+}
+
+/// Counts the used bytes of the user again. Returns whether the count was wrong.
+pub async fn fix_used_storage(pg_pool: &PgPool, user_id: i32) -> Result<bool, InternalError> {
+    let err = |_| InternalError("Failed to fix the used storage".to_string());
+    let mut tx = pg_pool.begin().await.map_err(err)?;
+    // Changing a file of the user locks the user too, so once it's locked
+    // all the changes to the files are committed and can be counted
+    sqlx::query!(
+        "SELECT id
+        FROM users
+        WHERE id = $1
+        FOR UPDATE;",
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(err)?;
+    let res = sqlx::query!(
+        "UPDATE users
+        SET used_bytes = get_used_bytes(id)
+        WHERE id = $1 AND used_bytes <> get_used_bytes(id);",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(err)?;
+    tx.commit().await.map_err(err)?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Fixes the wrong counts of used bytes. Returns the number of users that were fixed.
+pub async fn reconcile_used_storage(pg_pool: &PgPool) -> Result<usize, InternalError> {
+    let mut fixed = 0;
+    for w in get_wrong_usages(pg_pool).await? {
+        // The count may have been right already, it was changing while it was checked
+        if fix_used_storage(pg_pool, w.get_user_id()).await? {
+            fixed += 1;
+        }
+    }
+    Ok(fixed)
+}
+
+pub async fn delete_user(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    user_id: i32,
+) -> Result<(), InternalError> {
+    let err = |_| InternalError("Error while deleting user".to_string());
+    let mut tx = pg_pool.begin().await.map_err(err)?;
+    let blobs = files_model::delete_user_files(&mut tx, user_id)
+        .await
+        .map_err(err)?;
+    folders_model::delete_user_folders(&mut tx, user_id)
+        .await
+        .map_err(err)?;
     sqlx::query!(
         "DELETE FROM users
         WHERE id = $1;",
         user_id,
     )
-    .execute(pg_pool)
+    .execute(&mut *tx)
     .await
-    .map_err(|_| InternalError("Error while deleting user".to_string()))?;
-    Ok(())
+    .map_err(err)?;
+    tx.commit().await.map_err(err)?;
+    // The content is deleted once nothing references it anymore
+    blobs_model::purge_blobs(pg_pool, storage, &blobs).await
 }
 
 fn validate_username(username: &str) -> bool {
@@ -0,0 +1,180 @@
+use super::{blobs_model, file::File, file_version::FileVersion};
+use crate::{
+    errors::InternalError,
+    storage::{ContentReader, StorageBackend},
+};
+use sqlx::{PgConnection, PgPool};
+
+/// Returns the previous versions of the file, starting from the most recent one.
+pub async fn get_versions(
+    pg_pool: &PgPool,
+    file_id: i32,
+    user_id: i32,
+) -> Result<Vec<FileVersion>, InternalError> {
+    // The versions can be read by whoever can read the file
+    sqlx::query_as!(
+        FileVersion,
+        "SELECT *
+        FROM file_versions
+        WHERE fk_file = $1
+        AND fk_file IN (SELECT id FROM files WHERE id = $1 AND (fk_owner = $2 OR has_file_read_access($1, $2)))
+        ORDER BY last_modified DESC, id DESC;",
+        file_id,
+        user_id
+    )
+    .fetch_all(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the versions of the file".to_string()))
+}
+
+pub async fn get_version(
+    pg_pool: &PgPool,
+    version_id: i32,
+    file_id: i32,
+    user_id: i32,
+) -> Result<FileVersion, InternalError> {
+    sqlx::query_as!(
+        FileVersion,
+        "SELECT *
+        FROM file_versions
+        WHERE id = $1 AND fk_file = $2
+        AND fk_file IN (SELECT id FROM files WHERE id = $2 AND (fk_owner = $3 OR has_file_read_access($2, $3)));",
+        version_id,
+        file_id,
+        user_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .map_err(|_| InternalError("Failed to get the version of the file".to_string()))
+}
+
+/// Reads the content of the version, or only the given (inclusive) range of bytes.
+pub async fn read_version_content(
+    storage: &dyn StorageBackend,
+    version: &FileVersion,
+    range: Option<(u64, u64)>,
+) -> Result<ContentReader, InternalError> {
+    storage.get(&version.fk_blob, range).await
+}
+
+/// Makes the version the current content of the file.
+/// The content it replaces is kept as a version in its place.
+pub async fn restore_version(
+    pg_pool: &PgPool,
+    version_id: i32,
+    file_id: i32,
+    owner_id: i32,
+) -> Result<(), InternalError> {
+    let err = || InternalError("Failed to restore the version".to_string());
+    let mut tx = pg_pool.begin().await.map_err(|_| err())?;
+    let file = sqlx::query_as!(
+        File,
+        "SELECT id, name, file_type, mime_type, size, last_modified, starred, fk_owner,
+        fk_parent, fk_blob
+        FROM files
+        WHERE id = $1 AND fk_owner = $2
+        FOR UPDATE;",
+        file_id,
+        owner_id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| err())?;
+    // The blobs are swapped, so the number of references to them doesn't change
+    let version = sqlx::query_as!(
+        FileVersion,
+        "UPDATE file_versions new
+        SET file_type = $3, mime_type = $4, size = $5, last_modified = $6, fk_blob = $7
+        FROM file_versions old
+        WHERE new.id = old.id AND new.id = $1 AND new.fk_file = $2
+        RETURNING old.*;",
+        version_id,
+        file_id,
+        file.file_type,
+        file.mime_type,
+        file.size,
+        file.last_modified,
+        file.fk_blob
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| err())?;
+    sqlx::query!(
+        "UPDATE files
+        SET file_type = $2, mime_type = $3, size = $4, last_modified = CURRENT_TIMESTAMP,
+        fk_blob = $5
+        WHERE id = $1;",
+        file_id,
+        version.file_type,
+        version.mime_type,
+        version.size,
+        version.fk_blob
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| err())?;
+    tx.commit().await.map_err(|_| err())
+}
+
+/// Deletes the given version of the file, or all of them if no version is given.
+pub async fn delete_versions(
+    pg_pool: &PgPool,
+    storage: &dyn StorageBackend,
+    file_id: i32,
+    owner_id: i32,
+    version_id: Option<i32>,
+) -> Result<(), InternalError> {
+    let err = || InternalError("Failed to delete the versions of the file".to_string());
+    let mut tx = pg_pool.begin().await.map_err(|_| err())?;
+    let blobs: Vec<String> = sqlx::query!(
+        "DELETE FROM file_versions
+        WHERE fk_file = $1 AND ($3::integer IS NULL OR id = $3)
+        AND fk_file IN (SELECT id FROM files WHERE id = $1 AND fk_owner = $2)
+        RETURNING fk_blob;",
+        file_id,
+        owner_id,
+        version_id
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| err())?
+    .into_iter()
+    .map(|r| r.fk_blob)
+    .collect();
+    blobs_model::release_blobs(&mut tx, &blobs)
+        .await
+        .map_err(|_| err())?;
+    tx.commit().await.map_err(|_| err())?;
+    blobs_model::purge_blobs(pg_pool, storage, &blobs).await
+}
+
+/// Returns the size of all the previous versions of the user's files.
+pub async fn get_versions_size(pg_pool: &PgPool, owner_id: i32) -> Result<i64, InternalError> {
+    sqlx::query!(
+        r#"SELECT COALESCE(SUM(size), 0)::bigint as "size!"
+        FROM file_versions
+        WHERE fk_file IN (SELECT id FROM files WHERE fk_owner = $1);"#,
+        owner_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .map(|r| r.size)
+    .map_err(|_| InternalError("Failed to get the size of the versions".to_string()))
+}
+
+/// Keeps the current content of the file as a version, so it can be replaced.
+pub(super) async fn save_version(conn: &mut PgConnection, file: &File) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO file_versions (file_type, mime_type, size, last_modified, fk_file, fk_blob)
+        VALUES ($1, $2, $3, $4, $5, $6);",
+        file.file_type,
+        file.mime_type,
+        file.size,
+        file.last_modified,
+        file.id,
+        file.fk_blob
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
@@ -1,15 +1,20 @@
 mod api;
 
-use crate::models::RedisPool;
+use crate::{models::RedisPool, storage::Storage};
 use api::api;
 use axum::Router;
 use rand_chacha::ChaCha8Rng;
 use sqlx::PgPool;
 use tower_http::services::ServeDir;
 
-pub fn create_routes(pg_pool: PgPool, redis_pool: RedisPool, rng: ChaCha8Rng) -> Router {
+pub fn create_routes(
+    pg_pool: PgPool,
+    redis_pool: RedisPool,
+    rng: ChaCha8Rng,
+    storage: Storage,
+) -> Router {
     // Combine the routes
     Router::new()
-        .nest("/api", api(pg_pool, redis_pool, rng))
+        .nest("/api", api(pg_pool, redis_pool, rng, storage))
         .nest_service("/", ServeDir::new("public/dist"))
 }
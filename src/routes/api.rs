@@ -1,29 +1,58 @@
 mod auth;
 mod cloud;
+mod links;
+mod previews;
+mod resumable;
+mod search;
+mod share;
+mod starred;
+mod trash;
+mod usage;
+mod versions;
 
-use crate::{models::RedisPool, MAX_UPLOAD_MB};
+use crate::{
+    models::{RedisPool, SessionLocks},
+    storage::Storage,
+    MAX_UPLOAD_MB,
+};
 use auth::{auth_middleware, login, logout, me, me_delete, signup};
 use axum::{
     extract::DefaultBodyLimit,
     http::StatusCode,
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
 use cloud::{
-    file_delete, file_download, file_duplicate, file_move, file_rename, folder_delete, folder_move,
-    folder_new, folder_rename, folder_size, upload, view,
+    file_delete, file_download, file_duplicate, file_move, file_rename, folder_delete,
+    folder_download, folder_duplicate, folder_move, folder_new, folder_rename, folder_size, upload,
+    view,
 };
+use links::{file_link, folder_link, link_delete, links, public_download, public_link};
+use previews::{file_preview, file_thumbnail};
 use rand_chacha::ChaCha8Rng;
+use resumable::{
+    upload_session_cancel, upload_session_chunk, upload_session_finish, upload_session_new,
+    upload_session_status,
+};
+use search::search;
 use serde::Serialize;
+use share::{file_share, file_unshare, folder_share, folder_unshare, shared};
 use sqlx::PgPool;
+use starred::{file_star, file_unstar, folder_star, folder_unstar, starred};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use trash::{trash_empty, trash_restore};
+use usage::usage;
+use versions::{file_version_restore, file_versions, file_versions_delete};
 
 #[derive(Clone)]
 pub struct AppState {
     pub pg_pool: PgPool,
     pub redis_pool: RedisPool,
     pub rng: Arc<Mutex<ChaCha8Rng>>,
+    pub storage: Storage,
+    // The upload sessions receiving a chunk
+    pub session_locks: SessionLocks,
 }
 
 /// Data returned when something goes wrong.
@@ -52,11 +81,13 @@ impl ErrorResponse {
 // TODO: Consider this idea
 // pub type ApiResponse<T> = Result<(StatusCode, T), (StatusCode, Json<ErrorResponse>)>;
 
-pub fn api(pg_pool: PgPool, redis_pool: RedisPool, rng: ChaCha8Rng) -> Router {
+pub fn api(pg_pool: PgPool, redis_pool: RedisPool, rng: ChaCha8Rng, storage: Storage) -> Router {
     let state = AppState {
         pg_pool: pg_pool.clone(),
         redis_pool: redis_pool.clone(),
         rng: Arc::new(Mutex::new(rng)),
+        storage,
+        session_locks: SessionLocks::default(),
     };
     // Routes protected by the auth middleware (require authentication)
     let protected_routes = Router::new()
@@ -64,17 +95,47 @@ pub fn api(pg_pool: PgPool, redis_pool: RedisPool, rng: ChaCha8Rng) -> Router {
         .route("/me", get(me))
         .route("/me", delete(me_delete))
         .route("/upload", post(upload))
+        .route("/upload/session", post(upload_session_new))
+        .route("/upload/session", get(upload_session_status))
+        .route("/upload/session", put(upload_session_chunk))
+        .route("/upload/session", delete(upload_session_cancel))
+        .route("/upload/session/finish", post(upload_session_finish))
         .route("/view", get(view))
+        .route("/search", get(search))
+        .route("/usage", get(usage))
         .route("/folder/new", post(folder_new))
         .route("/folder/rename", patch(folder_rename))
         .route("/folder/size", get(folder_size))
         .route("/folder/move", patch(folder_move))
         .route("/folder/delete", delete(folder_delete))
+        .route("/folder/download", get(folder_download))
+        .route("/folder/duplicate", post(folder_duplicate))
         .route("/file/download", get(file_download))
+        .route("/file/thumbnail", get(file_thumbnail))
+        .route("/file/preview", get(file_preview))
         .route("/file/rename", patch(file_rename))
         .route("/file/move", patch(file_move))
         .route("/file/delete", delete(file_delete))
         .route("/file/duplicate", post(file_duplicate))
+        .route("/file/versions", get(file_versions))
+        .route("/file/versions", delete(file_versions_delete))
+        .route("/file/versions/restore", post(file_version_restore))
+        .route("/file/share", post(file_share))
+        .route("/file/unshare", delete(file_unshare))
+        .route("/folder/share", post(folder_share))
+        .route("/folder/unshare", delete(folder_unshare))
+        .route("/shared", get(shared))
+        .route("/file/star", post(file_star))
+        .route("/file/unstar", delete(file_unstar))
+        .route("/folder/star", post(folder_star))
+        .route("/folder/unstar", delete(folder_unstar))
+        .route("/starred", get(starred))
+        .route("/file/link", post(file_link))
+        .route("/folder/link", post(folder_link))
+        .route("/link", delete(link_delete))
+        .route("/links", get(links))
+        .route("/trash/restore", post(trash_restore))
+        .route("/trash/empty", delete(trash_empty))
         .layer(axum::middleware::from_fn(move |req, next| {
             auth_middleware(req, next, redis_pool.clone())
         }))
@@ -83,6 +144,8 @@ pub fn api(pg_pool: PgPool, redis_pool: RedisPool, rng: ChaCha8Rng) -> Router {
     Router::new()
         .route("/signup", post(signup))
         .route("/login", post(login))
+        .route("/public/link", get(public_link))
+        .route("/public/download", get(public_download))
         .nest("/", protected_routes)
         .with_state(state)
 }
@@ -2,7 +2,7 @@ use super::{AppState, ErrorResponse};
 use crate::{
     errors::{LoginError, SignupError},
     models::{folders_model, sessions_model, users_model, RedisPool},
-    MAX_STORAGE_MB, MAX_UPLOAD_MB,
+    MAX_UPLOAD_MB,
 };
 use axum::{
     body::Body,
@@ -40,6 +40,7 @@ struct MeResponse {
     trash_folder_id: i32,
     max_upload_mb: usize,
     max_storage_mb: i64,
+    plan: Option<String>,
 }
 
 pub type AuthState = (u128, i32);
@@ -195,7 +196,10 @@ pub async fn me(
     State(state): State<AppState>,
 ) -> impl IntoResponse {
     let res = users_model::get_user_by_id(&state.pg_pool, user_id).await;
-    let Ok(folders) = folders_model::get_root_folders(&state.pg_pool, user_id).await else {
+    let (Ok(personal_folder), Ok(trash_folder)) = (
+        folders_model::get_personal_folder(&state.pg_pool, user_id).await,
+        folders_model::get_trash_folder(&state.pg_pool, user_id).await,
+    ) else {
         return StatusCode::INTERNAL_SERVER_ERROR.into_response();
     };
     match res {
@@ -206,11 +210,11 @@ pub async fn me(
                     Json(MeResponse {
                         username: u.get_username().clone(),
                         email: u.get_email().clone(),
-                        // This works as long as there are only 2 root folders
-                        personal_folder_id: folders[0].get_id(),
-                        trash_folder_id: folders[1].get_id(),
+                        personal_folder_id: personal_folder.get_id(),
+                        trash_folder_id: trash_folder.get_id(),
                         max_upload_mb: *MAX_UPLOAD_MB,
-                        max_storage_mb: *MAX_STORAGE_MB,
+                        max_storage_mb: u.get_max_storage_mb(),
+                        plan: u.get_plan().clone(),
                     }),
                 )
                     .into_response()
@@ -227,7 +231,7 @@ pub async fn me_delete(
     Extension((session_id, user_id)): Extension<AuthState>,
     State(state): State<AppState>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    users_model::delete_user(&state.pg_pool, user_id)
+    users_model::delete_user(&state.pg_pool, state.storage.as_ref(), user_id)
         .await
         .map_err(|_| ErrorResponse::internal_err())?;
     Ok(login_response(session_id))
@@ -1,24 +1,39 @@
 use super::{auth::AuthState, AppState, ErrorResponse};
 use crate::{
-    errors::FileError,
-    models::{files_model, folders_model},
-    MAX_STORAGE_MB, MAX_UPLOAD_MB,
+    errors::{FileError, QuotaError, TrashError},
+    models::{
+        files_model, folders_model, reservations_model, shares_model, trash_model, users_model,
+        versions_model, Position, Reservation, Sort, SortKey, TempFile,
+    },
+    MAX_UPLOAD_MB,
 };
 use axum::{
-    body::Bytes,
-    extract::{Multipart, Query, State},
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
+    body::StreamBody,
+    extract::{multipart::Field, Multipart, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Extension, Json,
 };
+use rand_core::RngCore;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use tokio_util::io::ReaderStream;
+
+const ARCHIVE_BUFFER_SIZE: usize = 64 * 1024;
+const MAX_VIEW_LIMIT: i64 = 1000;
 
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ViewQuery {
-    parent_folder_id: i32,
+    parent_folder_id: Option<i32>,
     folders_only: Option<bool>,
+    shared: Option<bool>,
+    // One of "name", "size", "last-modified" or "type"
+    sort: Option<String>,
+    // Either "asc" or "desc"
+    direction: Option<String>,
+    limit: Option<i64>,
+    cursor: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -26,6 +41,18 @@ pub struct ViewQuery {
 pub struct ViewResponse {
     files: Vec<File>,
     folders: Vec<Folder>,
+    // Only set when there's another page
+    next_cursor: Option<String>,
+}
+
+/// Where the next page of a folder listing starts. The folders are listed before the files.
+/// The client gets it as an opaque string and sends it back as is.
+#[derive(Serialize, Deserialize)]
+struct ViewCursor {
+    sort: String,
+    descending: bool,
+    files: bool,
+    after: Option<(String, String, i32)>,
 }
 
 #[derive(Serialize)]
@@ -34,13 +61,30 @@ pub struct File {
     pub id: i32,
     pub name: String,
     pub file_type: Option<String>,
-    pub size: i32,
+    pub mime_type: String,
+    pub size: i64,
     pub last_modified: String,
     pub starred: bool,
     pub owner_id: i32,
     pub parent_id: i32,
 }
 
+impl From<&crate::models::File> for File {
+    fn from(f: &crate::models::File) -> Self {
+        File {
+            id: f.get_id(),
+            name: f.get_name().clone(),
+            file_type: f.get_file_type().clone(),
+            mime_type: f.get_mime_type().clone(),
+            size: f.get_size(),
+            last_modified: f.get_last_modified().to_string(),
+            starred: f.get_starred(),
+            owner_id: f.get_fk_owner(),
+            parent_id: f.get_fk_parent(),
+        }
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Folder {
@@ -52,12 +96,35 @@ pub struct Folder {
     pub parent_id: i32,
 }
 
+/// Root folders can't be converted, they're never sent to the users.
+impl From<&crate::models::Folder> for Folder {
+    fn from(f: &crate::models::Folder) -> Self {
+        Folder {
+            id: f.get_id(),
+            name: f.get_name().clone(),
+            last_modified: f.get_last_modified().to_string(),
+            starred: f.get_starred(),
+            owner_id: f.get_fk_owner(),
+            parent_id: f
+                .get_fk_parent()
+                .expect("Root folders aren't sent to the users"),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct IdQuery {
     id: i32,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DownloadQuery {
+    id: i32,
+    version_id: Option<i32>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NewFolderData {
@@ -101,8 +168,10 @@ pub struct IdFilterQuery {
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct IdData {
+pub struct DuplicateData {
     id: i32,
+    // The copy is put next to the original when there's no destination
+    destination_id: Option<i32>,
 }
 
 pub async fn upload(
@@ -110,58 +179,74 @@ pub async fn upload(
     State(state): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    // The file can't be larger than the space left, so it's checked while it's being received
+    let space_left = get_space_left(&state.pg_pool, user_id).await?;
     // Data to be extracted from the multipart
     let mut file_name: Option<String> = None;
-    let mut content: Option<Bytes> = None;
+    let mut content: Option<TempFile> = None;
     let mut parent_folder: Option<i32> = None;
     // Parse the multipart data
-    while let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|_| ErrorResponse::internal_err())?
-    {
-        // Get the name of the field
-        let field_name = field.name().ok_or(ErrorResponse::response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Internal error.",
-        ))?;
+    let res = loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break Ok(()),
+            Err(_) => break Err(ErrorResponse::internal_err()),
+        };
         // For each field get their data (if present, None otherwise)
-        match field_name {
-            "file" => {
+        match field.name() {
+            Some("file") if content.is_none() => {
                 file_name = field.file_name().map(|s| s.to_string());
-                content = field.bytes().await.ok();
+                let id = state.rng.lock().await.next_u64();
+                let Ok(temp_file) = TempFile::create(id).await else {
+                    break Err(ErrorResponse::internal_err());
+                };
+                // Write the content to disk while it's received
+                let temp_file = content.insert(temp_file);
+                if let Err(e) = receive_content(field, temp_file, space_left).await {
+                    break Err(e);
+                }
             }
-            "parent" => parent_folder = field.text().await.ok().and_then(|n| n.parse().ok()),
-            _ => continue,
+            Some("parent") => parent_folder = field.text().await.ok().and_then(|n| n.parse().ok()),
+            Some(_) => continue,
+            None => break Err(ErrorResponse::internal_err()),
         };
-    }
-    // If some of the necessary data from the fields is missing return an error
-    let (Some(file_name), Some(content), Some(parent_folder_id)) =
-        (file_name, content, parent_folder)
-    else {
-        return Err(ErrorResponse::response(
-            StatusCode::BAD_REQUEST,
-            "Invalid form data.",
-        ));
     };
-    // Check the size of the file (Axum should handle this already)
-    if content.len() > (*MAX_UPLOAD_MB * 1_000_000) {
-        return Err(ErrorResponse::response(
-            StatusCode::PAYLOAD_TOO_LARGE,
-            &format!("The file can't be larger than {} MB.", *MAX_UPLOAD_MB),
-        ));
-    }
-    // Check if the user has enough space to upload the file
-    check_size(&state.pg_pool, user_id, content.len() as i64).await?;
+    // If something went wrong or some of the necessary data is missing return an error
+    let content = match (res, content) {
+        (Ok(()), Some(content)) => content,
+        (res, content) => {
+            if let Some(content) = content {
+                content.discard().await;
+            }
+            return Err(res.err().unwrap_or(invalid_form_data()));
+        }
+    };
+    let (Some(file_name), Some(parent_folder_id)) = (file_name, parent_folder) else {
+        content.discard().await;
+        return Err(invalid_form_data());
+    };
+    // Other files may have been uploaded in the meantime
+    let reservation = match reserve_storage(&state.pg_pool, user_id, content.get_size()).await {
+        Ok(reservation) => reservation,
+        Err(e) => {
+            content.discard().await;
+            return Err(e);
+        }
+    };
     // Add the file to the databases
     let res = files_model::new_file(
         &state.pg_pool,
+        state.storage.as_ref(),
         &file_name,
-        &content,
+        content,
         parent_folder_id,
         user_id,
+        &reservation,
     )
     .await;
+    if res.is_err() {
+        let _ = reservations_model::release_storage(&state.pg_pool, reservation).await;
+    }
     match res {
         Ok(_) => Ok(StatusCode::CREATED),
         Err(e) => match e {
@@ -169,6 +254,10 @@ pub async fn upload(
                 StatusCode::BAD_REQUEST,
                 "Invalid file name.",
             )),
+            FileError::NotFound => Err(ErrorResponse::response(
+                StatusCode::NOT_FOUND,
+                "Folder not found.",
+            )),
             FileError::InternalError => Err(ErrorResponse::internal_err()),
         },
     }
@@ -179,22 +268,101 @@ pub async fn view(
     State(state): State<AppState>,
     Query(params): Query<ViewQuery>,
 ) -> Result<(StatusCode, Json<ViewResponse>), (StatusCode, Json<ErrorResponse>)> {
-    // Fetch the folders from the database first
-    let raw_folders = folders_model::get_folders(&state.pg_pool, params.parent_folder_id, user_id)
-        .await
-        .map_err(|_| ErrorResponse::internal_err())?;
+    // When the shared view is requested, list the top level items shared with the user
+    let parent_folder_id = match (params.shared, params.parent_folder_id) {
+        (Some(true), _) => None,
+        (_, Some(id)) => Some(id),
+        _ => {
+            return Err(ErrorResponse::response(
+                StatusCode::BAD_REQUEST,
+                "Missing parent folder.",
+            ))
+        }
+    };
+    let bad_request = |message| ErrorResponse::response(StatusCode::BAD_REQUEST, message);
+    // The shared items come from several folders, so they have no position to go on from
+    if parent_folder_id.is_none() && (params.limit.is_some() || params.cursor.is_some()) {
+        return Err(bad_request("The shared view isn't paginated."));
+    }
+    let sort_name = params.sort.unwrap_or_else(|| "name".to_string());
+    let key = match sort_name.as_str() {
+        "name" => SortKey::Name,
+        "size" => SortKey::Size,
+        "last-modified" => SortKey::LastModified,
+        "type" => SortKey::Type,
+        _ => return Err(bad_request("Invalid sort.")),
+    };
+    let descending = match params.direction.as_deref() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        _ => return Err(bad_request("Invalid direction.")),
+    };
+    let sort = Sort { key, descending };
+    if params
+        .limit
+        .is_some_and(|l| !(1..=MAX_VIEW_LIMIT).contains(&l))
+    {
+        return Err(bad_request("Invalid limit."));
+    }
+    let cursor = match params.cursor {
+        Some(c) => {
+            let cursor = decode_cursor(&c).ok_or_else(|| bad_request("Invalid cursor."))?;
+            // The positions only make sense in the order they were taken from
+            if cursor.sort != sort_name || cursor.descending != descending {
+                return Err(bad_request("Invalid cursor."));
+            }
+            Some(cursor)
+        }
+        None => None,
+    };
+    let in_files = cursor.as_ref().is_some_and(|c| c.files);
+    let after = cursor
+        .and_then(|c| c.after)
+        .map(|(value, name, id)| Position { value, name, id });
+    if after
+        .as_ref()
+        .is_some_and(|p| !sort.is_valid_position(p, !in_files))
+    {
+        return Err(bad_request("Invalid cursor."));
+    }
+    let new_cursor = |files: bool, after: Option<Position>| {
+        encode_cursor(&ViewCursor {
+            sort: sort_name.clone(),
+            descending,
+            files,
+            after: after.map(|p| (p.value, p.name, p.id)),
+        })
+    };
+    // One more item than needed is fetched to know if there's another page
+    let mut limit = params.limit.map(|l| l + 1);
+    let mut next_cursor = None;
+    // Fetch the folders from the database first, unless the cursor is past them
+    let mut raw_folders = match parent_folder_id {
+        Some(_) if in_files => Ok(Vec::new()),
+        Some(id) => {
+            let after = after.as_ref();
+            folders_model::get_folders(&state.pg_pool, id, user_id, sort, after, limit).await
+        }
+        None => shares_model::get_shared_folders(&state.pg_pool, user_id).await,
+    }
+    .map_err(|_| ErrorResponse::internal_err())?;
+    if let Some(l) = limit {
+        if raw_folders.len() as i64 >= l {
+            raw_folders.truncate(l as usize - 1);
+            let last = raw_folders.last().map(|f| sort.folder_position(f));
+            next_cursor = Some(new_cursor(false, last));
+            limit = Some(0);
+        } else {
+            // The rest of the page is filled with files
+            limit = Some(l - raw_folders.len() as i64);
+        }
+    }
     // Map the folder models to objects that can be sent to the user
     let folders = raw_folders
         .iter()
-        .map(|f| Folder {
-            id: f.get_id(),
-            name: f.get_name().clone(),
-            last_modified: f.get_last_modified().to_string(),
-            starred: f.get_starred(),
-            owner_id: f.get_fk_owner(),
-            // All of these folders have a parent because that's how they were selected
-            parent_id: f.get_fk_parent().unwrap(),
-        })
+        // All of these folders have a parent because that's how they were selected
+        // (root folders can't be shared)
+        .map(Folder::from)
         .collect();
     // If folders_only is specified and it's true
     if let Some(folders_only) = params.folders_only {
@@ -205,30 +373,41 @@ pub async fn view(
                 Json(ViewResponse {
                     files: Vec::new(),
                     folders,
+                    next_cursor,
                 }),
             ));
         }
     }
     // Otherwise, fetch the files too
-    let raw_files = files_model::get_files(&state.pg_pool, params.parent_folder_id, user_id)
-        .await
-        .map_err(|_| ErrorResponse::internal_err())?;
+    let mut raw_files = match (parent_folder_id, limit) {
+        // The page is full, the files start on the next one
+        (Some(_), Some(0)) => {
+            next_cursor = next_cursor.or_else(|| Some(new_cursor(true, None)));
+            Ok(Vec::new())
+        }
+        (Some(id), _) => {
+            let after = after.as_ref().filter(|_| in_files);
+            files_model::get_files(&state.pg_pool, id, user_id, sort, after, limit).await
+        }
+        (None, _) => shares_model::get_shared_files(&state.pg_pool, user_id).await,
+    }
+    .map_err(|_| ErrorResponse::internal_err())?;
+    if let Some(l) = limit.filter(|l| *l > 0 && raw_files.len() as i64 >= *l) {
+        raw_files.truncate(l as usize - 1);
+        let last = raw_files.last().map(|f| sort.file_position(f));
+        next_cursor = Some(new_cursor(true, last));
+    }
     // Map the file models to objects that can be sent to the user
-    let files = raw_files
-        .iter()
-        .map(|f| File {
-            id: f.get_id(),
-            name: f.get_name().clone(),
-            file_type: f.get_file_type().clone(),
-            size: f.get_size(),
-            last_modified: f.get_last_modified().to_string(),
-            starred: f.get_starred(),
-            owner_id: f.get_fk_owner(),
-            parent_id: f.get_fk_parent(),
-        })
-        .collect();
+    let files = raw_files.iter().map(File::from).collect();
     // Send the files and folders
-    Ok((StatusCode::OK, Json(ViewResponse { files, folders })))
+    Ok((
+        StatusCode::OK,
+        Json(ViewResponse {
+            files,
+            folders,
+            next_cursor,
+        }),
+    ))
 }
 
 pub async fn folder_new(
@@ -242,6 +421,9 @@ pub async fn folder_new(
             FileError::NameError => {
                 ErrorResponse::response(StatusCode::BAD_REQUEST, "Invalid folder name.")
             }
+            FileError::NotFound => {
+                ErrorResponse::response(StatusCode::NOT_FOUND, "Folder not found.")
+            }
             FileError::InternalError => ErrorResponse::internal_err(),
         })?;
     Ok(StatusCode::CREATED)
@@ -258,6 +440,9 @@ pub async fn folder_rename(
             FileError::NameError => {
                 ErrorResponse::response(StatusCode::BAD_REQUEST, "Invalid folder name.")
             }
+            FileError::NotFound => {
+                ErrorResponse::response(StatusCode::NOT_FOUND, "Folder not found.")
+            }
             FileError::InternalError => ErrorResponse::internal_err(),
         })?;
     Ok(StatusCode::OK)
@@ -274,6 +459,9 @@ pub async fn file_rename(
             FileError::NameError => {
                 ErrorResponse::response(StatusCode::BAD_REQUEST, "Invalid file name.")
             }
+            FileError::NotFound => {
+                ErrorResponse::response(StatusCode::NOT_FOUND, "File not found.")
+            }
             FileError::InternalError => ErrorResponse::internal_err(),
         })?;
     Ok(StatusCode::OK)
@@ -282,17 +470,108 @@ pub async fn file_rename(
 pub async fn file_download(
     Extension((_, user_id)): Extension<AuthState>,
     State(state): State<AppState>,
-    Query(IdQuery { id: file_id }): Query<IdQuery>,
-) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    let (name, content) = files_model::get_file(&state.pg_pool, file_id, user_id)
+    Query(query): Query<DownloadQuery>,
+    req_headers: HeaderMap,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let file = files_model::get_file(&state.pg_pool, query.id, user_id)
         .await
-        .map_err(|_| ErrorResponse::internal_err())?;
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        "Content-Disposition",
-        format!("attachment; filename={}", name).parse().unwrap(),
+        .map_err(|_| ErrorResponse::response(StatusCode::NOT_FOUND, "File not found."))?;
+    // A previous version of the file can be downloaded instead of the current one
+    let version = match query.version_id {
+        Some(version_id) => Some(
+            versions_model::get_version(&state.pg_pool, version_id, query.id, user_id)
+                .await
+                .map_err(|_| {
+                    ErrorResponse::response(StatusCode::NOT_FOUND, "Version not found.")
+                })?,
+        ),
+        None => None,
+    };
+    let (size, modified, mime_type) = match &version {
+        Some(v) => (
+            v.get_size() as u64,
+            v.get_last_modified(),
+            v.get_mime_type(),
+        ),
+        None => (
+            file.get_size() as u64,
+            file.get_last_modified(),
+            file.get_mime_type(),
+        ),
+    };
+    let etag = format!(
+        "\"{}-{}-{}\"",
+        file.get_id(),
+        size,
+        modified.and_utc().timestamp()
     );
-    Ok((StatusCode::OK, headers, content))
+    let last_modified = modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Disposition", attachment_header(file.get_name()));
+    headers.insert("Content-Type", mime_type.parse().unwrap());
+    // The browser mustn't guess another type, like HTML from a text file
+    headers.insert("X-Content-Type-Options", "nosniff".parse().unwrap());
+    headers.insert("Accept-Ranges", "bytes".parse().unwrap());
+    headers.insert("ETag", etag.parse().unwrap());
+    headers.insert("Last-Modified", last_modified.parse().unwrap());
+    // The range is ignored if the file changed since the client got the If-Range validator
+    let range_allowed = req_headers
+        .get("If-Range")
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|v| v == etag || v == last_modified);
+    let range = req_headers
+        .get("Range")
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| range_allowed)
+        .map(|r| parse_range(r, size));
+    let range = match range {
+        Some(Ok(range)) => range,
+        Some(Err(_)) => {
+            headers.insert(
+                "Content-Range",
+                format!("bytes */{}", size).parse().unwrap(),
+            );
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+        }
+        // No range (or an unsupported one), so the whole file is sent
+        None => None,
+    };
+    let content = match &version {
+        Some(v) => versions_model::read_version_content(state.storage.as_ref(), v, range).await,
+        None => files_model::read_file_content(state.storage.as_ref(), &file, range).await,
+    }
+    .map_err(|_| ErrorResponse::internal_err())?;
+    let body = StreamBody::new(ReaderStream::new(content));
+    match range {
+        Some((start, end)) => {
+            headers.insert("Content-Length", (end - start + 1).into());
+            headers.insert(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, size).parse().unwrap(),
+            );
+            Ok((StatusCode::PARTIAL_CONTENT, headers, body).into_response())
+        }
+        None => {
+            headers.insert("Content-Length", size.into());
+            Ok((StatusCode::OK, headers, body).into_response())
+        }
+    }
+}
+
+pub async fn folder_download(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(IdQuery { id: folder_id }): Query<IdQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let folder = folders_model::get_readable_folder(&state.pg_pool, folder_id, user_id)
+        .await
+        .map_err(|_| ErrorResponse::response(StatusCode::NOT_FOUND, "Folder not found."))?;
+    Ok(archive_response(
+        state,
+        folder_id,
+        folder.get_fk_owner(),
+        folder.get_name(),
+    ))
 }
 
 pub async fn folder_size(
@@ -334,9 +613,20 @@ pub async fn file_delete(
     State(state): State<AppState>,
     Query(IdQuery { id: file_id }): Query<IdQuery>,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    files_model::delete_file(&state.pg_pool, file_id, user_id)
+    trash_model::trash_file(&state.pg_pool, state.storage.as_ref(), file_id, user_id)
         .await
-        .map_err(|_| ErrorResponse::internal_err())?;
+        .map_err(|e| match e {
+            TrashError::NotFound => {
+                ErrorResponse::response(StatusCode::NOT_FOUND, "File not found.")
+            }
+            TrashError::RootFolder => {
+                ErrorResponse::response(StatusCode::BAD_REQUEST, "Root folders can't be deleted.")
+            }
+            TrashError::NotInTrash => {
+                ErrorResponse::response(StatusCode::CONFLICT, "The file isn't in the trash.")
+            }
+            TrashError::InternalError => ErrorResponse::internal_err(),
+        })?;
     Ok(StatusCode::OK)
 }
 
@@ -345,55 +635,271 @@ pub async fn folder_delete(
     State(state): State<AppState>,
     Query(query): Query<DeleteQuery>,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    folders_model::delete_folder(
+    trash_model::trash_folder(
         &state.pg_pool,
+        state.storage.as_ref(),
         query.id,
         user_id,
         query.preserve_parent.unwrap_or(false),
     )
     .await
-    .map_err(|_| ErrorResponse::internal_err())?;
+    .map_err(|e| match e {
+        TrashError::NotFound => ErrorResponse::response(StatusCode::NOT_FOUND, "Folder not found."),
+        TrashError::RootFolder => {
+            ErrorResponse::response(StatusCode::BAD_REQUEST, "Root folders can't be deleted.")
+        }
+        TrashError::NotInTrash => ErrorResponse::response(
+            StatusCode::CONFLICT,
+            "Only the content of folders in the trash can be deleted.",
+        ),
+        TrashError::InternalError => ErrorResponse::internal_err(),
+    })?;
     Ok(StatusCode::OK)
 }
 
 pub async fn file_duplicate(
     Extension((_, user_id)): Extension<AuthState>,
     State(state): State<AppState>,
-    Json(IdData { id: file_id }): Json<IdData>,
+    Json(data): Json<DuplicateData>,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    let file = files_model::get_file_by_id(&state.pg_pool, file_id, user_id)
+    let file = files_model::get_file_by_id(&state.pg_pool, data.id, user_id)
         .await
         .map_err(|_| ErrorResponse::internal_err())?;
-    check_size(&state.pg_pool, user_id, i64::from(file.get_size())).await?;
-    files_model::duplicate_file(&state.pg_pool, file_id, user_id)
+    let reservation = reserve_storage(&state.pg_pool, user_id, file.get_size()).await?;
+    let res = files_model::duplicate_file(
+        &state.pg_pool,
+        data.id,
+        user_id,
+        data.destination_id,
+        &reservation,
+    )
+    .await;
+    if res.is_err() {
+        let _ = reservations_model::release_storage(&state.pg_pool, reservation).await;
+    }
+    res.map_err(|_| ErrorResponse::internal_err())?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn folder_duplicate(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<DuplicateData>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    // The whole folder must fit before anything is copied
+    let size = folders_model::folder_size(&state.pg_pool, data.id, user_id, None)
         .await
         .map_err(|_| ErrorResponse::internal_err())?;
+    let reservation = reserve_storage(&state.pg_pool, user_id, size).await?;
+    let res = folders_model::duplicate_folder(
+        &state.pg_pool,
+        data.id,
+        user_id,
+        data.destination_id,
+        &reservation,
+    )
+    .await;
+    if res.is_err() {
+        let _ = reservations_model::release_storage(&state.pg_pool, reservation).await;
+    }
+    res.map_err(|_| ErrorResponse::internal_err())?;
     Ok(StatusCode::OK)
 }
 
-async fn check_size(
-    pg_pool: &PgPool,
-    user_id: i32,
-    file_size: i64,
+/// Parses a single "bytes" range, returning the first and last byte (inclusive) to send.
+/// Returns None if the range is malformed or has more than one range, since in that case
+/// the header can be ignored. Returns an error if the range can't be satisfied.
+fn parse_range(range: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let (start, end) = match (start.trim(), end.trim()) {
+        // Suffix range: the last N bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return Err(()),
+            Ok(n) => (size.saturating_sub(n), size.saturating_sub(1)),
+            Err(_) => return Ok(None),
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, size.saturating_sub(1)),
+            Err(_) => return Ok(None),
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+            _ => return Ok(None),
+        },
+    };
+    if size == 0 || start >= size {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+/// Writes the content of the multipart field to the temp file, chunk by chunk.
+async fn receive_content(
+    mut field: Field<'_>,
+    content: &mut TempFile,
+    space_left: i64,
 ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
-    let folders = folders_model::get_root_folders(pg_pool, user_id)
+    let max_upload = (*MAX_UPLOAD_MB * 1_000_000) as i64;
+    while let Some(chunk) = field
+        .chunk()
         .await
-        .map_err(|_| ErrorResponse::internal_err())?;
-    let mut used_storage = 0;
-    for f in folders {
-        used_storage += folders_model::folder_size(pg_pool, f.get_id(), user_id, None)
+        .map_err(|_| ErrorResponse::internal_err())?
+    {
+        content
+            .write_chunk(&chunk)
             .await
             .map_err(|_| ErrorResponse::internal_err())?;
+        // Check the size of the file (Axum should handle this already)
+        if content.get_size() > max_upload {
+            return Err(ErrorResponse::response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                &format!("The file can't be larger than {} MB.", *MAX_UPLOAD_MB),
+            ));
+        }
+        // Check if the user has enough space to upload the file
+        if content.get_size() > space_left {
+            return Err(not_enough_space(space_left));
+        }
     }
-    let space_left = (*MAX_STORAGE_MB * 1_000_000) - used_storage;
+    Ok(())
+}
+
+pub(super) async fn check_size(
+    pg_pool: &PgPool,
+    user_id: i32,
+    file_size: i64,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let space_left = get_space_left(pg_pool, user_id).await?;
     if space_left < file_size {
-        return Err(ErrorResponse::response(
-            StatusCode::PAYLOAD_TOO_LARGE,
-            &format!(
-                "Not enough space available. {} MB left.",
-                space_left / 1_000_000
-            ),
-        ));
+        return Err(not_enough_space(space_left));
     }
     Ok(())
 }
+
+/// Sets the storage aside for files that are about to be added. Unlike `check_size`,
+/// this holds when several files are added at the same time.
+pub(super) async fn reserve_storage(
+    pg_pool: &PgPool,
+    user_id: i32,
+    bytes: i64,
+) -> Result<Reservation, (StatusCode, Json<ErrorResponse>)> {
+    reservations_model::reserve_storage(pg_pool, user_id, bytes)
+        .await
+        .map_err(|e| match e {
+            QuotaError::NotEnoughSpace(space_left) => not_enough_space(space_left),
+            QuotaError::InternalError => ErrorResponse::internal_err(),
+        })
+}
+
+async fn get_space_left(
+    pg_pool: &PgPool,
+    user_id: i32,
+) -> Result<i64, (StatusCode, Json<ErrorResponse>)> {
+    let user = users_model::get_user_by_id(pg_pool, user_id)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?
+        .ok_or_else(ErrorResponse::internal_err)?;
+    let used_storage = users_model::get_used_storage(pg_pool, user_id)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    Ok((user.get_max_storage_mb() * 1_000_000) - used_storage)
+}
+
+/// Streams a ZIP archive of the folder.
+/// The archive is written in the background and sent to the client while it's created.
+pub(super) fn archive_response(
+    state: AppState,
+    folder_id: i32,
+    owner_id: i32,
+    folder_name: &str,
+) -> impl IntoResponse {
+    let (writer, reader) = tokio::io::duplex(ARCHIVE_BUFFER_SIZE);
+    tokio::spawn(async move {
+        let res = folders_model::write_folder_archive(
+            &state.pg_pool,
+            state.storage.as_ref(),
+            folder_id,
+            owner_id,
+            writer,
+        )
+        .await;
+        if let Err(e) = res {
+            eprintln!("{}", e);
+        }
+    });
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/zip".parse().unwrap());
+    headers.insert(
+        "Content-Disposition",
+        attachment_header(&format!("{}.zip", folder_name)),
+    );
+    (
+        StatusCode::OK,
+        headers,
+        StreamBody::new(ReaderStream::new(reader)),
+    )
+}
+
+/// Returns a Content-Disposition value that downloads the content as a file with the name.
+/// Clients that don't understand the UTF-8 name get one with the other characters replaced.
+pub(super) fn attachment_header(file_name: &str) -> HeaderValue {
+    let fallback: String = file_name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let mut encoded = String::new();
+    for b in file_name.bytes() {
+        // The characters allowed as is by RFC 5987
+        if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    HeaderValue::from_str(&format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    ))
+    .unwrap_or_else(|_| HeaderValue::from_static("attachment"))
+}
+
+fn encode_cursor(cursor: &ViewCursor) -> String {
+    let json = serde_json::to_vec(cursor).unwrap_or_default();
+    json.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_cursor(cursor: &str) -> Option<ViewCursor> {
+    if !cursor.len().is_multiple_of(2) || !cursor.is_ascii() {
+        return None;
+    }
+    let json = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    serde_json::from_slice(&json).ok()
+}
+
+fn invalid_form_data() -> (StatusCode, Json<ErrorResponse>) {
+    ErrorResponse::response(StatusCode::BAD_REQUEST, "Invalid form data.")
+}
+
+fn not_enough_space(space_left: i64) -> (StatusCode, Json<ErrorResponse>) {
+    ErrorResponse::response(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        &format!(
+            "Not enough space available. {} MB left.",
+            space_left.max(0) / 1_000_000
+        ),
+    )
+}
@@ -0,0 +1,263 @@
+use super::{
+    auth::AuthState,
+    cloud::{archive_response, attachment_header},
+    AppState, ErrorResponse,
+};
+use crate::{
+    errors::LinkError,
+    models::{files_model, folders_model, links_model, Link},
+};
+use axum::{
+    body::StreamBody,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio_util::io::ReaderStream;
+
+const PASSWORD_HEADER: &str = "X-Link-Password";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkData {
+    id: i32,
+    password: Option<String>,
+    // Unix timestamp, in seconds
+    expires_at: Option<i64>,
+    max_downloads: Option<i32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LinkQuery {
+    id: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TokenQuery {
+    token: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinksResponse {
+    links: Vec<LinkResponse>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkResponse {
+    id: i32,
+    token: String,
+    file_id: Option<i32>,
+    folder_id: Option<i32>,
+    name: String,
+    has_password: bool,
+    expires_at: Option<String>,
+    max_downloads: Option<i32>,
+    downloads: i32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicLinkResponse {
+    name: String,
+    is_folder: bool,
+    size: i64,
+    expires_at: Option<String>,
+    downloads_left: Option<i32>,
+}
+
+pub async fn file_link(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<LinkData>,
+) -> Result<(StatusCode, Json<LinkResponse>), (StatusCode, Json<ErrorResponse>)> {
+    validate_link_data(&data)?;
+    // The rng is only locked while the token is drawn
+    let token = links_model::new_token(&mut *state.rng.lock().await);
+    let link = links_model::new_file_link(
+        &state.pg_pool,
+        &token,
+        data.id,
+        user_id,
+        data.password.as_deref().filter(|p| !p.is_empty()),
+        data.expires_at,
+        data.max_downloads,
+    )
+    .await
+    .map_err(new_link_err)?;
+    Ok((StatusCode::CREATED, Json(to_link_response(&link))))
+}
+
+pub async fn folder_link(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<LinkData>,
+) -> Result<(StatusCode, Json<LinkResponse>), (StatusCode, Json<ErrorResponse>)> {
+    validate_link_data(&data)?;
+    let token = links_model::new_token(&mut *state.rng.lock().await);
+    let link = links_model::new_folder_link(
+        &state.pg_pool,
+        &token,
+        data.id,
+        user_id,
+        data.password.as_deref().filter(|p| !p.is_empty()),
+        data.expires_at,
+        data.max_downloads,
+    )
+    .await
+    .map_err(new_link_err)?;
+    Ok((StatusCode::CREATED, Json(to_link_response(&link))))
+}
+
+pub async fn links(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<LinksResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let links = links_model::get_links(&state.pg_pool, user_id)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?
+        .iter()
+        .map(to_link_response)
+        .collect();
+    Ok((StatusCode::OK, Json(LinksResponse { links })))
+}
+
+pub async fn link_delete(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<LinkQuery>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    links_model::delete_link(&state.pg_pool, query.id, user_id)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    Ok(StatusCode::OK)
+}
+
+/// Describes what a link gives access to. Doesn't require an account.
+pub async fn public_link(
+    State(state): State<AppState>,
+    Query(query): Query<TokenQuery>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<PublicLinkResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let link = links_model::get_link(&state.pg_pool, &query.token, get_password(&headers))
+        .await
+        .map_err(link_err)?;
+    let size = match (link.get_fk_file(), link.get_fk_folder()) {
+        (Some(file_id), _) => {
+            files_model::get_file_by_id(&state.pg_pool, *file_id, link.get_fk_owner())
+                .await
+                .map(|f| f.get_size())
+        }
+        (_, Some(folder_id)) => {
+            folders_model::folder_size(&state.pg_pool, *folder_id, link.get_fk_owner(), None).await
+        }
+        _ => Ok(0),
+    }
+    .map_err(|_| ErrorResponse::internal_err())?;
+    Ok((
+        StatusCode::OK,
+        Json(PublicLinkResponse {
+            name: link.get_item_name().clone(),
+            is_folder: link.get_fk_folder().is_some(),
+            size,
+            expires_at: link.get_expires_at().map(|t| t.to_string()),
+            downloads_left: link
+                .get_max_downloads()
+                .map(|max| max - link.get_downloads()),
+        }),
+    ))
+}
+
+/// Downloads the file (or the folder as a ZIP archive) of a link. Doesn't require an account.
+pub async fn public_download(
+    State(state): State<AppState>,
+    Query(query): Query<TokenQuery>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let link = links_model::use_link(&state.pg_pool, &query.token, get_password(&headers))
+        .await
+        .map_err(link_err)?;
+    if let Some(folder_id) = *link.get_fk_folder() {
+        let owner_id = link.get_fk_owner();
+        return Ok(
+            archive_response(state, folder_id, owner_id, link.get_item_name()).into_response(),
+        );
+    }
+    let file_id = link.get_fk_file().ok_or_else(ErrorResponse::internal_err)?;
+    let file = files_model::get_file_by_id(&state.pg_pool, file_id, link.get_fk_owner())
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    let content = files_model::read_file_content(state.storage.as_ref(), &file, None)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Disposition", attachment_header(file.get_name()));
+    headers.insert("Content-Type", file.get_mime_type().parse().unwrap());
+    headers.insert("X-Content-Type-Options", "nosniff".parse().unwrap());
+    headers.insert("Content-Length", file.get_size().into());
+    let body = StreamBody::new(ReaderStream::new(content));
+    Ok((StatusCode::OK, headers, body).into_response())
+}
+
+fn validate_link_data(data: &LinkData) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| ErrorResponse::internal_err())?
+        .as_secs() as i64;
+    if data.expires_at.is_some_and(|t| t <= now) {
+        return Err(ErrorResponse::response(
+            StatusCode::BAD_REQUEST,
+            "The expiry date must be in the future.",
+        ));
+    }
+    if data.max_downloads.is_some_and(|max| max <= 0) {
+        return Err(ErrorResponse::response(
+            StatusCode::BAD_REQUEST,
+            "The maximum number of downloads must be positive.",
+        ));
+    }
+    Ok(())
+}
+
+fn get_password(headers: &HeaderMap) -> Option<&str> {
+    headers.get(PASSWORD_HEADER).and_then(|v| v.to_str().ok())
+}
+
+fn to_link_response(link: &Link) -> LinkResponse {
+    LinkResponse {
+        id: link.get_id(),
+        token: link.get_token().clone(),
+        file_id: *link.get_fk_file(),
+        folder_id: *link.get_fk_folder(),
+        name: link.get_item_name().clone(),
+        has_password: link.has_password(),
+        expires_at: link.get_expires_at().map(|t| t.to_string()),
+        max_downloads: *link.get_max_downloads(),
+        downloads: link.get_downloads(),
+    }
+}
+
+fn link_err(e: LinkError) -> (StatusCode, Json<ErrorResponse>) {
+    match e {
+        LinkError::NotFound => ErrorResponse::response(StatusCode::NOT_FOUND, "Link not found."),
+        LinkError::WrongPassword => {
+            ErrorResponse::response(StatusCode::UNAUTHORIZED, "Wrong password.")
+        }
+        LinkError::InternalError => ErrorResponse::internal_err(),
+    }
+}
+
+fn new_link_err(e: LinkError) -> (StatusCode, Json<ErrorResponse>) {
+    match e {
+        LinkError::NotFound => {
+            ErrorResponse::response(StatusCode::NOT_FOUND, "File or folder not found.")
+        }
+        e => link_err(e),
+    }
+}
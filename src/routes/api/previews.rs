@@ -0,0 +1,152 @@
+use super::{auth::AuthState, AppState, ErrorResponse};
+use crate::{
+    errors::ThumbnailError,
+    models::{decode_text, detect_language, files_model, thumbnails_model},
+};
+use axum::{
+    body::StreamBody,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+use tokio_util::io::ReaderStream;
+
+const THUMBNAIL_SIZES: [u32; 4] = [64, 128, 256, 512];
+const DEFAULT_THUMBNAIL_SIZE: u32 = 256;
+const DEFAULT_PREVIEW_KB: u64 = 64;
+const MAX_PREVIEW_KB: u64 = 1024;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ThumbnailQuery {
+    id: i32,
+    // One of THUMBNAIL_SIZES, in pixels
+    size: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PreviewQuery {
+    id: i32,
+    // How much of the beginning of the file is returned
+    max_kb: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewResponse {
+    content: String,
+    // The encoding the content was decoded from
+    encoding: String,
+    language: Option<String>,
+    // In the returned content
+    lines: usize,
+    // Whether the file goes on after the content
+    truncated: bool,
+}
+
+pub async fn file_thumbnail(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<ThumbnailQuery>,
+    req_headers: HeaderMap,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let size = query.size.unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+    if !THUMBNAIL_SIZES.contains(&size) {
+        return Err(ErrorResponse::response(
+            StatusCode::BAD_REQUEST,
+            "Invalid thumbnail size.",
+        ));
+    }
+    let file = files_model::get_file(&state.pg_pool, query.id, user_id)
+        .await
+        .map_err(|_| ErrorResponse::response(StatusCode::NOT_FOUND, "File not found."))?;
+    // The thumbnail changes with the content, which changes the modification date
+    let etag = format!(
+        "\"{}-{}-{}\"",
+        file.get_id(),
+        file.get_last_modified().and_utc().timestamp(),
+        size
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert("ETag", etag.parse().unwrap());
+    // Kept by the browser, but checked each time since the URL doesn't change with the content
+    headers.insert("Cache-Control", "private, no-cache".parse().unwrap());
+    let not_modified = req_headers
+        .get("If-None-Match")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim() == etag));
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+    let temp_id = state.rng.lock().await.next_u64();
+    let (thumbnail, content) = thumbnails_model::get_thumbnail(
+        &state.pg_pool,
+        state.storage.as_ref(),
+        &file,
+        size,
+        temp_id,
+    )
+    .await
+    .map_err(|e| match e {
+        ThumbnailError::Unsupported => ErrorResponse::response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "There's no thumbnail for this file.",
+        ),
+        ThumbnailError::InternalError => ErrorResponse::internal_err(),
+    })?;
+    headers.insert("Content-Type", thumbnail.get_mime_type().parse().unwrap());
+    headers.insert("X-Content-Type-Options", "nosniff".parse().unwrap());
+    headers.insert("Content-Length", thumbnail.get_bytes().into());
+    let body = StreamBody::new(ReaderStream::new(content));
+    Ok((StatusCode::OK, headers, body).into_response())
+}
+
+pub async fn file_preview(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<PreviewQuery>,
+) -> Result<(StatusCode, HeaderMap, Json<PreviewResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let max_kb = query.max_kb.unwrap_or(DEFAULT_PREVIEW_KB);
+    if !(1..=MAX_PREVIEW_KB).contains(&max_kb) {
+        return Err(ErrorResponse::response(
+            StatusCode::BAD_REQUEST,
+            "Invalid preview length.",
+        ));
+    }
+    let file = files_model::get_file(&state.pg_pool, query.id, user_id)
+        .await
+        .map_err(|_| ErrorResponse::response(StatusCode::NOT_FOUND, "File not found."))?;
+    let cannot_preview = || {
+        ErrorResponse::response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "This file can't be previewed.",
+        )
+    };
+    // SVG images are text, so their markup can be shown too
+    if file.get_file_type().as_deref() != Some("Text") && file.get_mime_type() != "image/svg+xml" {
+        return Err(cannot_preview());
+    }
+    let head = files_model::read_file_head(state.storage.as_ref(), &file, max_kb * 1024)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    let truncated = (head.len() as i64) < file.get_size();
+    let (content, encoding) = decode_text(&head, truncated).ok_or_else(cannot_preview)?;
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Disposition", "inline".parse().unwrap());
+    headers.insert("X-Content-Type-Options", "nosniff".parse().unwrap());
+    Ok((
+        StatusCode::OK,
+        headers,
+        Json(PreviewResponse {
+            lines: content.lines().count(),
+            content,
+            encoding: encoding.to_string(),
+            language: detect_language(file.get_name()).map(str::to_string),
+            truncated,
+        }),
+    ))
+}
@@ -0,0 +1,248 @@
+use super::{
+    auth::AuthState,
+    cloud::{check_size, reserve_storage},
+    AppState, ErrorResponse,
+};
+use crate::{
+    errors::FileError,
+    models::{reservations_model, upload_sessions_model, TempFile},
+    MAX_RESUMABLE_UPLOAD_MB,
+};
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Query, RawBody, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSessionData {
+    name: String,
+    size: i64,
+    parent_id: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SessionQuery {
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChunkQuery {
+    id: String,
+    offset: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionData {
+    id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionResponse {
+    id: String,
+    offset: i64,
+    size: i64,
+}
+
+pub async fn upload_session_new(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<NewSessionData>,
+) -> Result<(StatusCode, Json<SessionResponse>), (StatusCode, Json<ErrorResponse>)> {
+    if data.size < 0 {
+        return Err(ErrorResponse::response(
+            StatusCode::BAD_REQUEST,
+            "Invalid file size.",
+        ));
+    }
+    if data.size > *MAX_RESUMABLE_UPLOAD_MB * 1_000_000 {
+        return Err(ErrorResponse::response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!(
+                "The file can't be larger than {} MB.",
+                *MAX_RESUMABLE_UPLOAD_MB
+            ),
+        ));
+    }
+    // Check if the user has enough space before anything is uploaded
+    check_size(&state.pg_pool, user_id, data.size).await?;
+    let id = state.rng.lock().await.next_u64();
+    let session = upload_sessions_model::new_session(
+        &state.pg_pool,
+        id,
+        &data.name,
+        data.size,
+        data.parent_id,
+        user_id,
+    )
+    .await
+    .map_err(|e| match e {
+        FileError::NameError => {
+            ErrorResponse::response(StatusCode::BAD_REQUEST, "Invalid file name.")
+        }
+        FileError::NotFound => ErrorResponse::response(StatusCode::NOT_FOUND, "Folder not found."),
+        FileError::InternalError => ErrorResponse::internal_err(),
+    })?;
+    Ok((
+        StatusCode::CREATED,
+        Json(SessionResponse {
+            id: session.get_id().clone(),
+            offset: 0,
+            size: session.get_size(),
+        }),
+    ))
+}
+
+pub async fn upload_session_status(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<SessionQuery>,
+) -> Result<(StatusCode, Json<SessionResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let session = upload_sessions_model::get_session(&state.pg_pool, &query.id, user_id)
+        .await
+        .map_err(|_| session_not_found())?;
+    let content = upload_sessions_model::open_session_content(&session)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    Ok((
+        StatusCode::OK,
+        Json(SessionResponse {
+            id: session.get_id().clone(),
+            offset: content.get_size(),
+            size: session.get_size(),
+        }),
+    ))
+}
+
+pub async fn upload_session_chunk(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<ChunkQuery>,
+    RawBody(body): RawBody,
+) -> Result<(StatusCode, Json<SessionResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let session = upload_sessions_model::get_session(&state.pg_pool, &query.id, user_id)
+        .await
+        .map_err(|_| session_not_found())?;
+    // Two chunks sent at once could otherwise both be written at the same offset
+    let Some(_lock) = state.session_locks.try_lock(&session) else {
+        return Err(ErrorResponse::response(
+            StatusCode::CONFLICT,
+            "Another chunk is being received.",
+        ));
+    };
+    let res = match upload_sessions_model::open_session_content(&session).await {
+        Ok(mut content) => receive_chunk(body, &mut content, query.offset, session.get_size())
+            .await
+            .map(|_| content.get_size()),
+        Err(_) => Err(ErrorResponse::internal_err()),
+    };
+    Ok((
+        StatusCode::OK,
+        Json(SessionResponse {
+            id: session.get_id().clone(),
+            offset: res?,
+            size: session.get_size(),
+        }),
+    ))
+}
+
+pub async fn upload_session_finish(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<SessionData>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let session = upload_sessions_model::get_session(&state.pg_pool, &data.id, user_id)
+        .await
+        .map_err(|_| session_not_found())?;
+    let content = upload_sessions_model::open_session_content(&session)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    if content.get_size() != session.get_size() {
+        return Err(ErrorResponse::response(
+            StatusCode::CONFLICT,
+            &format!(
+                "The upload is incomplete, {} of {} bytes received.",
+                content.get_size(),
+                session.get_size()
+            ),
+        ));
+    }
+    // Other files may have been uploaded in the meantime
+    let reservation = reserve_storage(&state.pg_pool, user_id, content.get_size()).await?;
+    let res = upload_sessions_model::finish_session(
+        &state.pg_pool,
+        state.storage.as_ref(),
+        &session,
+        content,
+        &reservation,
+    )
+    .await;
+    if res.is_err() {
+        let _ = reservations_model::release_storage(&state.pg_pool, reservation).await;
+    }
+    res.map_err(|e| match e {
+        FileError::NameError => {
+            ErrorResponse::response(StatusCode::BAD_REQUEST, "Invalid file name.")
+        }
+        FileError::NotFound => ErrorResponse::response(StatusCode::NOT_FOUND, "Folder not found."),
+        FileError::InternalError => ErrorResponse::internal_err(),
+    })?;
+    Ok(StatusCode::CREATED)
+}
+
+pub async fn upload_session_cancel(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<SessionQuery>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let session = upload_sessions_model::get_session(&state.pg_pool, &query.id, user_id)
+        .await
+        .map_err(|_| session_not_found())?;
+    upload_sessions_model::cancel_session(&state.pg_pool, &session)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    Ok(StatusCode::OK)
+}
+
+/// Appends the request body to the content, without going past the size of the file.
+/// Whatever is written before an error happens is kept, so the upload can be resumed from there.
+async fn receive_chunk(
+    mut body: Body,
+    content: &mut TempFile,
+    offset: i64,
+    file_size: i64,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    // The chunk must continue exactly where the received content ends
+    if offset != content.get_size() {
+        return Err(ErrorResponse::response(
+            StatusCode::CONFLICT,
+            &format!("Wrong offset, expected {}.", content.get_size()),
+        ));
+    }
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| ErrorResponse::internal_err())?;
+        if content.get_size() + chunk.len() as i64 > file_size {
+            return Err(ErrorResponse::response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "The chunk goes past the end of the file.",
+            ));
+        }
+        content
+            .write_chunk(&chunk)
+            .await
+            .map_err(|_| ErrorResponse::internal_err())?;
+    }
+    Ok(())
+}
+
+fn session_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    ErrorResponse::response(StatusCode::NOT_FOUND, "Upload session not found.")
+}
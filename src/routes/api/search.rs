@@ -0,0 +1,96 @@
+use super::{auth::AuthState, cloud::File, AppState, ErrorResponse};
+use crate::models::{
+    files_model::{self, FileSearch},
+    folders_model,
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SearchQuery {
+    name: Option<String>,
+    file_type: Option<String>,
+    min_size: Option<i64>,
+    max_size: Option<i64>,
+    // Unix timestamps, in seconds
+    modified_after: Option<i64>,
+    modified_before: Option<i64>,
+    starred: Option<bool>,
+    folder_id: Option<i32>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResponse {
+    files: Vec<SearchResult>,
+    has_more: bool,
+}
+
+/// A file that was found, along with the path of the folder that contains it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    #[serde(flatten)]
+    file: File,
+    path: String,
+}
+
+pub async fn search(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<(StatusCode, Json<SearchResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    if !(1..=MAX_LIMIT).contains(&limit) || offset < 0 {
+        return Err(ErrorResponse::response(
+            StatusCode::BAD_REQUEST,
+            "Invalid limit or offset.",
+        ));
+    }
+    let search = FileSearch {
+        name: query
+            .name
+            .as_deref()
+            .map(str::trim)
+            .filter(|n| !n.is_empty()),
+        file_type: query.file_type.as_deref(),
+        min_size: query.min_size,
+        max_size: query.max_size,
+        modified_after: query.modified_after,
+        modified_before: query.modified_before,
+        starred: query.starred,
+        folder_id: query.folder_id,
+    };
+    // One more file is fetched to know if there's another page
+    let mut raw_files =
+        files_model::search_files(&state.pg_pool, user_id, &search, limit + 1, offset)
+            .await
+            .map_err(|_| ErrorResponse::internal_err())?;
+    let has_more = raw_files.len() as i64 > limit;
+    raw_files.truncate(limit as usize);
+    let mut parent_ids: Vec<i32> = raw_files.iter().map(|f| f.get_fk_parent()).collect();
+    parent_ids.sort_unstable();
+    parent_ids.dedup();
+    let paths = folders_model::get_folder_paths(&state.pg_pool, &parent_ids)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    let files = raw_files
+        .iter()
+        .map(|f| SearchResult {
+            file: File::from(f),
+            path: paths.get(&f.get_fk_parent()).cloned().unwrap_or_default(),
+        })
+        .collect();
+    Ok((StatusCode::OK, Json(SearchResponse { files, has_more })))
+}
@@ -0,0 +1,120 @@
+use super::{auth::AuthState, AppState, ErrorResponse};
+use crate::{errors::ShareError, models::shares_model};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareData {
+    id: i32,
+    username: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UnshareQuery {
+    id: i32,
+    username: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedResponse {
+    shares: Vec<Share>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Share {
+    id: i32,
+    file_id: Option<i32>,
+    folder_id: Option<i32>,
+    name: String,
+    username: String,
+}
+
+pub async fn file_share(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<ShareData>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    shares_model::share_file(&state.pg_pool, data.id, user_id, &data.username)
+        .await
+        .map_err(share_err)?;
+    Ok(StatusCode::CREATED)
+}
+
+pub async fn folder_share(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<ShareData>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    shares_model::share_folder(&state.pg_pool, data.id, user_id, &data.username)
+        .await
+        .map_err(share_err)?;
+    Ok(StatusCode::CREATED)
+}
+
+pub async fn file_unshare(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<UnshareQuery>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    shares_model::unshare_file(&state.pg_pool, query.id, user_id, &query.username)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn folder_unshare(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<UnshareQuery>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    shares_model::unshare_folder(&state.pg_pool, query.id, user_id, &query.username)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn shared(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<SharedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let raw_shares = shares_model::get_shares(&state.pg_pool, user_id)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    let shares = raw_shares
+        .iter()
+        .map(|s| Share {
+            id: s.get_id(),
+            file_id: *s.get_fk_file(),
+            folder_id: *s.get_fk_folder(),
+            name: s.get_item_name().clone(),
+            username: s.get_username().clone(),
+        })
+        .collect();
+    Ok((StatusCode::OK, Json(SharedResponse { shares })))
+}
+
+fn share_err(e: ShareError) -> (StatusCode, Json<ErrorResponse>) {
+    match e {
+        ShareError::NotFound => {
+            ErrorResponse::response(StatusCode::NOT_FOUND, "File or folder not found.")
+        }
+        ShareError::UserNotFound => {
+            ErrorResponse::response(StatusCode::NOT_FOUND, "This user does not exist.")
+        }
+        ShareError::SelfShare => {
+            ErrorResponse::response(StatusCode::BAD_REQUEST, "You can't share with yourself.")
+        }
+        ShareError::AlreadyShared => {
+            ErrorResponse::response(StatusCode::CONFLICT, "Already shared with this user.")
+        }
+        ShareError::InternalError => ErrorResponse::internal_err(),
+    }
+}
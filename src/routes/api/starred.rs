@@ -0,0 +1,161 @@
+use super::{
+    auth::AuthState,
+    cloud::{File, Folder},
+    AppState, ErrorResponse,
+};
+use crate::models::{files_model, folders_model};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StarData {
+    id: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UnstarQuery {
+    id: i32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StarredResponse {
+    files: Vec<StarredFile>,
+    folders: Vec<StarredFolder>,
+}
+
+/// A starred file, along with the path of the folder that contains it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StarredFile {
+    #[serde(flatten)]
+    file: File,
+    path: String,
+}
+
+/// A starred folder, along with the path of the folder that contains it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StarredFolder {
+    #[serde(flatten)]
+    folder: Folder,
+    path: String,
+}
+
+pub async fn file_star(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<StarData>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    set_file_starred(state, data.id, user_id, true).await
+}
+
+pub async fn file_unstar(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<UnstarQuery>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    set_file_starred(state, query.id, user_id, false).await
+}
+
+pub async fn folder_star(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<StarData>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    set_folder_starred(state, data.id, user_id, true).await
+}
+
+pub async fn folder_unstar(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<UnstarQuery>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    set_folder_starred(state, query.id, user_id, false).await
+}
+
+/// Lists the starred files and folders from the whole tree of the user.
+pub async fn starred(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<StarredResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let raw_files = files_model::get_starred_files(&state.pg_pool, user_id)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    let raw_folders = folders_model::get_starred_folders(&state.pg_pool, user_id)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    // The paths of all the parents are fetched at once
+    let mut parent_ids: Vec<i32> = raw_files
+        .iter()
+        .map(|f| f.get_fk_parent())
+        // Starred folders always have a parent because root folders can't be starred
+        .chain(raw_folders.iter().filter_map(|f| *f.get_fk_parent()))
+        .collect();
+    parent_ids.sort_unstable();
+    parent_ids.dedup();
+    let paths = folders_model::get_folder_paths(&state.pg_pool, &parent_ids)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    let path_of = |id: i32| paths.get(&id).cloned().unwrap_or_default();
+    let files = raw_files
+        .iter()
+        .map(|f| StarredFile {
+            file: File::from(f),
+            path: path_of(f.get_fk_parent()),
+        })
+        .collect();
+    let folders = raw_folders
+        .iter()
+        .filter_map(|f| {
+            let parent_id = (*f.get_fk_parent())?;
+            Some(StarredFolder {
+                folder: Folder::from(f),
+                path: path_of(parent_id),
+            })
+        })
+        .collect();
+    Ok((StatusCode::OK, Json(StarredResponse { files, folders })))
+}
+
+async fn set_file_starred(
+    state: AppState,
+    file_id: i32,
+    owner_id: i32,
+    starred: bool,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let found = files_model::set_file_starred(&state.pg_pool, file_id, owner_id, starred)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    if !found {
+        return Err(ErrorResponse::response(
+            StatusCode::NOT_FOUND,
+            "File not found.",
+        ));
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn set_folder_starred(
+    state: AppState,
+    folder_id: i32,
+    owner_id: i32,
+    starred: bool,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let found = folders_model::set_folder_starred(&state.pg_pool, folder_id, owner_id, starred)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    if !found {
+        return Err(ErrorResponse::response(
+            StatusCode::NOT_FOUND,
+            "Folder not found.",
+        ));
+    }
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,43 @@
+use super::{auth::AuthState, AppState, ErrorResponse};
+use crate::{errors::TrashError, models::trash_model};
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreData {
+    id: i32,
+    is_folder: Option<bool>,
+}
+
+pub async fn trash_restore(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<RestoreData>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    if data.is_folder.unwrap_or(false) {
+        trash_model::restore_folder(&state.pg_pool, data.id, user_id).await
+    } else {
+        trash_model::restore_file(&state.pg_pool, data.id, user_id).await
+    }
+    .map_err(|e| match e {
+        TrashError::NotFound => {
+            ErrorResponse::response(StatusCode::NOT_FOUND, "File or folder not found.")
+        }
+        TrashError::NotInTrash => {
+            ErrorResponse::response(StatusCode::CONFLICT, "Not in the trash.")
+        }
+        TrashError::RootFolder | TrashError::InternalError => ErrorResponse::internal_err(),
+    })?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn trash_empty(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    trash_model::empty_trash(&state.pg_pool, state.storage.as_ref(), user_id)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?;
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,176 @@
+use super::{
+    auth::AuthState,
+    cloud::{File, Folder},
+    AppState, ErrorResponse,
+};
+use crate::models::{files_model, folders_model, users_model, versions_model};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_TOP: i64 = 10;
+const MAX_TOP: i64 = 100;
+// The files are grouped by the first of these they were last modified within
+const AGES_DAYS: [i32; 3] = [7, 30, 365];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UsageQuery {
+    // How many of the largest files and folders to return
+    top: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageResponse {
+    used_bytes: i64,
+    max_storage_mb: i64,
+    space_left: i64,
+    trash_bytes: i64,
+    versions_bytes: i64,
+    by_type: Vec<TypeUsage>,
+    by_age: Vec<AgeUsage>,
+    largest_files: Vec<LargestFile>,
+    largest_folders: Vec<LargestFolder>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeUsage {
+    file_type: Option<String>,
+    files: i64,
+    bytes: i64,
+}
+
+/// The files last modified within the number of days, and not within a smaller one.
+/// There's no number of days for the files older than all of them.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgeUsage {
+    max_age_days: Option<i32>,
+    files: i64,
+    bytes: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LargestFile {
+    #[serde(flatten)]
+    file: File,
+    path: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LargestFolder {
+    #[serde(flatten)]
+    folder: Folder,
+    path: String,
+    size: i64,
+}
+
+pub async fn usage(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<UsageQuery>,
+) -> Result<(StatusCode, Json<UsageResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let top = query.top.unwrap_or(DEFAULT_TOP);
+    if !(1..=MAX_TOP).contains(&top) {
+        return Err(ErrorResponse::response(
+            StatusCode::BAD_REQUEST,
+            "Invalid top.",
+        ));
+    }
+    let pg_pool = &state.pg_pool;
+    let internal_err = |_| ErrorResponse::internal_err();
+    let user = users_model::get_user_by_id(pg_pool, user_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or_else(ErrorResponse::internal_err)?;
+    let used_bytes = users_model::get_used_storage(pg_pool, user_id)
+        .await
+        .map_err(internal_err)?;
+    let trash_folder = folders_model::get_trash_folder(pg_pool, user_id)
+        .await
+        .map_err(internal_err)?;
+    let trash_bytes = folders_model::folder_size(pg_pool, trash_folder.get_id(), user_id, None)
+        .await
+        .map_err(internal_err)?;
+    let versions_bytes = versions_model::get_versions_size(pg_pool, user_id)
+        .await
+        .map_err(internal_err)?;
+    let by_type = files_model::get_usage_by_type(pg_pool, user_id)
+        .await
+        .map_err(internal_err)?
+        .into_iter()
+        .map(|(file_type, files, bytes)| TypeUsage {
+            file_type,
+            files,
+            bytes,
+        })
+        .collect();
+    let by_age = files_model::get_usage_by_age(pg_pool, user_id, &AGES_DAYS)
+        .await
+        .map_err(internal_err)?
+        .into_iter()
+        .map(|(max_age_days, files, bytes)| AgeUsage {
+            max_age_days,
+            files,
+            bytes,
+        })
+        .collect();
+    let files = files_model::get_largest_files(pg_pool, user_id, top)
+        .await
+        .map_err(internal_err)?;
+    let folders = folders_model::get_largest_folders(pg_pool, user_id, top)
+        .await
+        .map_err(internal_err)?;
+    // Everything is shown with the path of the folder that contains it
+    let mut parent_ids: Vec<i32> = files
+        .iter()
+        .map(|f| f.get_fk_parent())
+        .chain(folders.iter().filter_map(|(f, _)| *f.get_fk_parent()))
+        .collect();
+    parent_ids.sort_unstable();
+    parent_ids.dedup();
+    let paths = folders_model::get_folder_paths(pg_pool, &parent_ids)
+        .await
+        .map_err(internal_err)?;
+    let path_of = |id: i32| paths.get(&id).cloned().unwrap_or_default();
+    let largest_files = files
+        .iter()
+        .map(|f| LargestFile {
+            file: File::from(f),
+            path: path_of(f.get_fk_parent()),
+        })
+        .collect();
+    let largest_folders = folders
+        .iter()
+        .filter_map(|(f, size)| {
+            let parent_id = (*f.get_fk_parent())?;
+            Some(LargestFolder {
+                folder: Folder::from(f),
+                path: path_of(parent_id),
+                size: *size,
+            })
+        })
+        .collect();
+    let max_storage_mb = user.get_max_storage_mb();
+    Ok((
+        StatusCode::OK,
+        Json(UsageResponse {
+            used_bytes,
+            max_storage_mb,
+            space_left: max_storage_mb * 1_000_000 - used_bytes,
+            trash_bytes,
+            versions_bytes,
+            by_type,
+            by_age,
+            largest_files,
+            largest_folders,
+        }),
+    ))
+}
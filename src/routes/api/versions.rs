@@ -0,0 +1,93 @@
+use super::{auth::AuthState, AppState, ErrorResponse};
+use crate::models::{files_model, versions_model};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VersionsQuery {
+    id: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DeleteVersionsQuery {
+    id: i32,
+    version_id: Option<i32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreVersionData {
+    id: i32,
+    version_id: i32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionsResponse {
+    versions: Vec<Version>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Version {
+    id: i32,
+    file_id: i32,
+    size: i64,
+    last_modified: String,
+}
+
+pub async fn file_versions(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<VersionsQuery>,
+) -> Result<(StatusCode, Json<VersionsResponse>), (StatusCode, Json<ErrorResponse>)> {
+    files_model::get_file(&state.pg_pool, query.id, user_id)
+        .await
+        .map_err(|_| ErrorResponse::response(StatusCode::NOT_FOUND, "File not found."))?;
+    let versions = versions_model::get_versions(&state.pg_pool, query.id, user_id)
+        .await
+        .map_err(|_| ErrorResponse::internal_err())?
+        .iter()
+        .map(|v| Version {
+            id: v.get_id(),
+            file_id: v.get_fk_file(),
+            size: v.get_size(),
+            last_modified: v.get_last_modified().to_string(),
+        })
+        .collect();
+    Ok((StatusCode::OK, Json(VersionsResponse { versions })))
+}
+
+pub async fn file_version_restore(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Json(data): Json<RestoreVersionData>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    versions_model::restore_version(&state.pg_pool, data.version_id, data.id, user_id)
+        .await
+        .map_err(|_| ErrorResponse::response(StatusCode::NOT_FOUND, "Version not found."))?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn file_versions_delete(
+    Extension((_, user_id)): Extension<AuthState>,
+    State(state): State<AppState>,
+    Query(query): Query<DeleteVersionsQuery>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    versions_model::delete_versions(
+        &state.pg_pool,
+        state.storage.as_ref(),
+        query.id,
+        user_id,
+        query.version_id,
+    )
+    .await
+    .map_err(|_| ErrorResponse::internal_err())?;
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,55 @@
+mod local;
+mod memory;
+mod s3;
+
+use crate::{
+    errors::InternalError,
+    models::{TempFile, FILES_FOLDER},
+    S3_ACCESS_KEY, S3_BUCKET, S3_ENDPOINT, S3_REGION, S3_SECRET_KEY,
+};
+use async_trait::async_trait;
+use std::{pin::Pin, sync::Arc};
+use tokio::io::AsyncRead;
+
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use s3::S3Storage;
+
+pub type ContentReader = Pin<Box<dyn AsyncRead + Send>>;
+pub type Storage = Arc<dyn StorageBackend>;
+
+/// Where the content of the files is kept, each content being identified by a key.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Stores the content of the temp file under the key, replacing what's already there.
+    /// The temp file is consumed even if that fails.
+    async fn put(&self, key: &str, content: TempFile) -> Result<(), InternalError>;
+
+    /// Reads the content stored under the key, or only the given (inclusive) range of bytes.
+    async fn get(
+        &self,
+        key: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<ContentReader, InternalError>;
+
+    /// Deletes the content stored under the key, if there's any.
+    async fn delete(&self, key: &str) -> Result<(), InternalError>;
+
+    /// Returns the key and the size of all the stored content.
+    async fn list(&self) -> Result<Vec<(String, u64)>, InternalError>;
+}
+
+pub fn init_storage(backend: &str) -> Storage {
+    match backend {
+        "local" => Arc::new(LocalStorage::new(FILES_FOLDER)),
+        "s3" => Arc::new(S3Storage::new(
+            &S3_ENDPOINT,
+            &S3_REGION,
+            &S3_BUCKET,
+            &S3_ACCESS_KEY,
+            &S3_SECRET_KEY,
+        )),
+        "memory" => Arc::new(MemoryStorage::default()),
+        _ => panic!("Unknown storage backend '{}'", backend),
+    }
+}
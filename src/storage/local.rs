@@ -0,0 +1,77 @@
+use super::{ContentReader, StorageBackend};
+use crate::{errors::InternalError, models::TempFile};
+use async_trait::async_trait;
+use std::{
+    io::{ErrorKind, SeekFrom},
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+/// Keeps the content in a folder of the local filesystem.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        LocalStorage {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn build_path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn put(&self, key: &str, content: TempFile) -> Result<(), InternalError> {
+        content.persist(&self.build_path(key)).await
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<ContentReader, InternalError> {
+        let err = || InternalError(format!("Failed to read content '{}'", key));
+        let mut file = fs::File::open(self.build_path(key))
+            .await
+            .map_err(|_| err())?;
+        match range {
+            Some((start, end)) => {
+                file.seek(SeekFrom::Start(start)).await.map_err(|_| err())?;
+                Ok(Box::pin(file.take(end - start + 1)))
+            }
+            None => Ok(Box::pin(file)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), InternalError> {
+        match fs::remove_file(self.build_path(key)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(InternalError(format!("Failed to delete content '{}'", key)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<(String, u64)>, InternalError> {
+        let err = |_| InternalError(format!("Failed to list '{}'", self.root.display()));
+        let mut entries = fs::read_dir(&self.root).await.map_err(err)?;
+        let mut contents = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(err)? {
+            let metadata = entry.metadata().await.map_err(err)?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // The temp files of the uploads are kept in the same folder
+            if metadata.is_file() && !name.ends_with(".part") {
+                contents.push((name, metadata.len()));
+            }
+        }
+        Ok(contents)
+    }
+}
@@ -0,0 +1,128 @@
+use super::{ContentReader, StorageBackend};
+use crate::{errors::InternalError, models::TempFile};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use std::{collections::HashMap, io::Cursor};
+use tokio::{fs, sync::RwLock};
+
+/// Keeps the content in memory, so it's lost when the server stops.
+/// Meant for testing.
+#[derive(Default)]
+pub struct MemoryStorage {
+    contents: RwLock<HashMap<String, Bytes>>,
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn put(&self, key: &str, content: TempFile) -> Result<(), InternalError> {
+        let res = fs::read(content.get_path()).await;
+        content.discard().await;
+        let data = res.map_err(|_| InternalError(format!("Failed to store content '{}'", key)))?;
+        self.contents
+            .write()
+            .await
+            .insert(key.to_string(), Bytes::from(data));
+        Ok(())
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<ContentReader, InternalError> {
+        let data = self
+            .contents
+            .read()
+            .await
+            .get(key)
+            .cloned()
+            .ok_or_else(|| InternalError(format!("Failed to read content '{}'", key)))?;
+        let data = match range {
+            // Like the other backends, the range stops at the end of the content
+            Some((start, end)) => {
+                let end = (end as usize).saturating_add(1).min(data.len());
+                data.slice((start as usize).min(end)..end)
+            }
+            None => data,
+        };
+        Ok(Box::pin(Cursor::new(data)))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), InternalError> {
+        self.contents.write().await.remove(key);
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<(String, u64)>, InternalError> {
+        let contents = self.contents.read().await;
+        Ok(contents
+            .iter()
+            .map(|(key, data)| (key.clone(), data.len() as u64))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::{OsRng, RngCore};
+    use tokio::io::AsyncReadExt;
+
+    async fn put(storage: &MemoryStorage, key: &str, data: &[u8]) {
+        // Kept out of the folder of the files
+        let mut content = TempFile::create_in(&std::env::temp_dir(), OsRng.next_u64())
+            .await
+            .unwrap();
+        content.write_chunk(data).await.unwrap();
+        storage.put(key, content).await.unwrap();
+    }
+
+    async fn get(storage: &MemoryStorage, key: &str, range: Option<(u64, u64)>) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut reader = storage.get(key, range).await.unwrap();
+        reader.read_to_end(&mut data).await.unwrap();
+        data
+    }
+
+    #[tokio::test]
+    async fn put_then_get() {
+        let storage = MemoryStorage::default();
+        put(&storage, "a", b"hello").await;
+        assert_eq!(get(&storage, "a", None).await, b"hello");
+        // Putting again replaces the content
+        put(&storage, "a", b"bye").await;
+        assert_eq!(get(&storage, "a", None).await, b"bye");
+        assert!(storage.get("b", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn get_range() {
+        let storage = MemoryStorage::default();
+        put(&storage, "a", b"0123456789").await;
+        assert_eq!(get(&storage, "a", Some((2, 5))).await, b"2345");
+        assert_eq!(get(&storage, "a", Some((9, 9))).await, b"9");
+        assert_eq!(get(&storage, "a", Some((7, 20))).await, b"789");
+        assert_eq!(get(&storage, "a", Some((15, 20))).await, b"");
+        assert_eq!(get(&storage, "a", Some((0, u64::MAX))).await, b"0123456789");
+    }
+
+    #[tokio::test]
+    async fn delete() {
+        let storage = MemoryStorage::default();
+        put(&storage, "a", b"hello").await;
+        storage.delete("a").await.unwrap();
+        assert!(storage.get("a", None).await.is_err());
+        // Deleting what isn't there isn't an error
+        storage.delete("a").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn list() {
+        let storage = MemoryStorage::default();
+        put(&storage, "a", b"hello").await;
+        put(&storage, "b", b"").await;
+        let mut contents = storage.list().await.unwrap();
+        contents.sort();
+        assert_eq!(contents, [("a".to_string(), 5), ("b".to_string(), 0)]);
+    }
+}
@@ -0,0 +1,109 @@
+use super::{ContentReader, StorageBackend};
+use crate::{errors::InternalError, models::TempFile};
+use async_trait::async_trait;
+use aws_sdk_s3::{
+    config::{Credentials, Region, RequestChecksumCalculation, ResponseChecksumValidation},
+    primitives::ByteStream,
+    Client,
+};
+
+/// Keeps the content in a bucket of an S3-compatible object storage (AWS, MinIO...).
+pub struct S3Storage {
+    client: Client,
+    bucket: String,
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        bucket: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Self {
+        let credentials = Credentials::new(access_key, secret_key, None, None, "env");
+        // Path-style requests and optional checksums are supported by every implementation
+        let config = aws_sdk_s3::Config::builder()
+            .endpoint_url(endpoint)
+            .region(Region::new(region.to_string()))
+            .credentials_provider(credentials)
+            .force_path_style(true)
+            .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+            .response_checksum_validation(ResponseChecksumValidation::WhenRequired)
+            .build();
+        S3Storage {
+            client: Client::from_conf(config),
+            bucket: bucket.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    async fn put(&self, key: &str, content: TempFile) -> Result<(), InternalError> {
+        let res = match ByteStream::from_path(content.get_path()).await {
+            Ok(body) => self
+                .client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .body(body)
+                .send()
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        content.discard().await;
+        res.map_err(|e| InternalError(format!("Failed to store content '{}': {}", key, e)))
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<ContentReader, InternalError> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_range(range.map(|(start, end)| format!("bytes={}-{}", start, end)))
+            .send()
+            .await
+            .map_err(|e| InternalError(format!("Failed to read content '{}': {}", key, e)))?;
+        Ok(Box::pin(object.body.into_async_read()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), InternalError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| InternalError(format!("Failed to delete content '{}': {}", key, e)))?;
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<(String, u64)>, InternalError> {
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .into_paginator()
+            .send();
+        let mut contents = Vec::new();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| {
+                InternalError(format!("Failed to list bucket '{}': {}", self.bucket, e))
+            })?;
+            for object in page.contents() {
+                if let Some(key) = object.key() {
+                    contents.push((key.to_string(), object.size().unwrap_or(0) as u64));
+                }
+            }
+        }
+        Ok(contents)
+    }
+}